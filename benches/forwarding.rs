@@ -0,0 +1,123 @@
+//! source to build position lookups of a bundle of many sources: scan of all sourcemap
+//! tokens (`Build::forward_src_position` before index of source tokens) against the index
+
+use std::collections::HashSet;
+
+use async_lsp::lsp_types::{Position, Url as Uri};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+use glscript_language_server::builder::{Build, BuildOptionsBuilder};
+use glscript_language_server::proxy::{DEFAULT_SCRIPT_FILENAME, PROXY_WORKSPACE};
+use glscript_language_server::state::State;
+use glscript_language_server::types::Source;
+
+const SOURCES_COUNT: usize = 150;
+
+const SCRIPT: &str = "var a%i = 'x %a%i y';\n\
+    function f%i(b) {\n  return `t ${b}\n  u` + /* c\n  d */ b;\n}\n\
+    var t = #text\n  `%a%i` ${x}\n#endtext;\n";
+
+/// `Build::forward_src_position` before index of source tokens
+fn forward_src_position_by_scan(
+    source_map: &sourcemap::SourceMap,
+    pos: &Position,
+    pos_source: &Source,
+) -> Option<Position> {
+    let sources = source_map.sources().map(|s| Source::new(s.into()));
+    let sources = sources.collect::<HashSet<_>>();
+    let mut token: Option<sourcemap::Token> = None;
+
+    if !sources.contains(pos_source) {
+        return None;
+    }
+
+    for t in source_map.tokens() {
+        if t.get_source() != Some(pos_source) {
+            continue;
+        }
+        if t.get_src_line() == pos.line && t.get_src_col() <= pos.character {
+            token = Some(t);
+        }
+        if t.get_src_line() > pos.line {
+            break;
+        }
+    }
+
+    token.map(|t| {
+        let line = t.get_dst_line();
+        let character = t.get_dst_col() + (pos.character - t.get_src_col());
+        Position::new(line, character)
+    })
+}
+
+/// bundle and its sourcemap (read from written map) with positions of each source line
+fn bundle() -> (Build, sourcemap::SourceMap, Vec<(Source, Position)>) {
+    let root = std::env::temp_dir().join(format!("glscript-bench-{}", std::process::id()));
+    let proxy_ws = root.join(PROXY_WORKSPACE);
+    std::fs::create_dir_all(proxy_ws.join("lib")).unwrap();
+    std::fs::create_dir_all(root.join("lib")).unwrap();
+    std::fs::write(proxy_ws.join(DEFAULT_SCRIPT_FILENAME), "").unwrap();
+    std::fs::write(proxy_ws.join(DEFAULT_SCRIPT_FILENAME.to_lowercase()), "").unwrap();
+
+    let mut main = String::new();
+    for i in 0..SOURCES_COUNT {
+        let script = SCRIPT.replace("%i", &i.to_string());
+        std::fs::write(root.join(format!("lib/s{i}.js")), script).unwrap();
+        main.push_str(&format!("#include <lib/s{i}.js>\n"));
+    }
+    std::fs::write(root.join("main.js"), main).unwrap();
+
+    let root = dunce::canonicalize(root).unwrap();
+    let state = State::default();
+    state.initialize_project(&Uri::from_file_path(&root).unwrap(), None);
+    state.initialize_include_roots(None);
+    state.initialize_defines(None);
+    state.initialize_region_kinds(None);
+
+    let uri = Uri::from_file_path(root.join("main.js")).unwrap();
+    let build = Build::create(BuildOptionsBuilder::init(&uri, &state)).unwrap();
+    let map_path = build
+        .save_with_source_map(&state, &root.join("out.js"))
+        .unwrap();
+    let map_file = std::fs::File::open(map_path).unwrap();
+    let mut source_map = sourcemap::SourceMap::from_reader(map_file).unwrap();
+    // written sources are paths relative to out dir
+    for id in 0..source_map.get_source_count() {
+        let source = source_map.get_source(id).unwrap();
+        let source = source.trim_start_matches("./").to_lowercase();
+        source_map.set_source(id, &source);
+    }
+    let _ = std::fs::remove_dir_all(&root);
+
+    let positions = (0..SOURCES_COUNT).flat_map(|i| {
+        let source = Source::new(format!("lib/s{i}.js"));
+        (0..SCRIPT.lines().count() as u32).map(move |l| (source.clone(), Position::new(l, 4)))
+    });
+    (build, source_map, positions.collect())
+}
+
+fn forward_src_position(c: &mut Criterion) {
+    let (build, source_map, positions) = bundle();
+    let mut group = c.benchmark_group("forward_src_position");
+
+    group.bench_function("scan", |b| {
+        b.iter(|| {
+            let forwarded = positions.iter();
+            let forwarded =
+                forwarded.filter_map(|(s, p)| forward_src_position_by_scan(&source_map, p, s));
+            forwarded.count()
+        })
+    });
+    group.bench_function("index", |b| {
+        b.iter(|| {
+            let forwarded = positions.iter();
+            let forwarded = forwarded.filter_map(|(s, p)| build.forward_src_position(p, s));
+            forwarded.count()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, forward_src_position);
+criterion_main!(benches);
@@ -1,8 +0,0 @@
-#include <some/script.js>
-
-var message =
-#text
-Hello world
-#endtext
-
-message;
@@ -1,16 +1,22 @@
 use async_lsp::lsp_types::Url as Uri;
 use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
+use crate::state::{State, relative_literal};
 use crate::types::{Source, SourceHash};
-use crate::{builder::emit::Stack, state::State};
 
+pub use cache::BuildCache;
+use forwarding::SourceTokensIndex;
 use options_builder::BuildOptions;
 pub use options_builder::BuildOptionsBuilder;
 use source_map_builder::SourceMapBuilder;
 
+pub use emit::{ContentSegment, SourceMapSegment, Stack, template_escapes};
 use emit::{Context, Emit, EmitResult};
 
+mod cache;
 mod emit;
 mod forwarding;
 mod options_builder;
@@ -28,12 +34,33 @@ pub struct Build {
     /// Shows how any [`Source`] resolved by [`Stack`]
     pub sources_stack: HashMap<Source, Stack>,
 
+    /// Include statements skipped by build because of already visited [`Source`]
+    pub include_issues: Vec<IncludeIssue>,
+
     source_map: sourcemap::SourceMap,
+    src_index: SourceTokensIndex,
     tokens_count: usize,
 }
 
 type PatternSources = HashSet<SourceHash>;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IncludeIssueKind {
+    /// target is the root or one of sources of includer [`Stack`]
+    Cycle,
+    /// target already included by includer itself, its dependencies or default document
+    Redundant,
+}
+
+#[derive(Debug, Clone, Constructor, Serialize, Deserialize)]
+pub struct IncludeIssue {
+    pub kind: IncludeIssueKind,
+    pub includer: Source,
+    pub target: Source,
+    /// [`Stack`] of includer ending with the skipped include statement of target
+    pub stack: Stack,
+}
+
 impl Build {
     pub fn create(mut opt_builder: BuildOptionsBuilder) -> anyhow::Result<Self> {
         if opt_builder.options.pat.is_none() {
@@ -80,6 +107,44 @@ impl Build {
         std::fs::create_dir_all(debug_filepath.parent().unwrap()).unwrap();
         std::fs::write(debug_filepath.clone(), build).unwrap();
     }
+
+    /// writes content to `out_path` and standalone sourcemap with sources content
+    /// next to it (`<out_path>.map`), returns path of sourcemap
+    pub fn save_with_source_map(&self, state: &State, out_path: &Path) -> anyhow::Result<PathBuf> {
+        let out_dir = out_path.parent().filter(|p| !p.as_os_str().is_empty());
+        let out_dir = out_dir.unwrap_or(Path::new("."));
+        std::fs::create_dir_all(out_dir)?;
+
+        let file_name = out_path.file_name().and_then(|n| n.to_str());
+        let file_name = file_name.ok_or_else(|| anyhow::anyhow!("invalid out file name"))?;
+        let map_path = out_path.with_added_extension("map");
+
+        // sources are on-disk paths relative to out dir (not lowercased sources)
+        let out_dir = dunce::canonicalize(out_dir)?;
+        let mut source_map = self.source_map.clone();
+        for id in 0..source_map.get_source_count() {
+            let Some(source) = source_map.get_source(id) else {
+                continue;
+            };
+
+            let path = state.get_source_path(&Source::new(source.into()));
+            let doc_uri = state.path_to_uri(&path)?;
+            let contents = state.get_doc(&doc_uri)?.buffer.to_string();
+            source_map.set_source_contents(id, Some(&contents));
+            source_map.set_source(id, &relative_literal(&out_dir, &path));
+        }
+        source_map.set_file(Some(file_name));
+
+        let mut sm_json = Vec::new();
+        source_map.to_writer(&mut sm_json)?;
+
+        let map_name = map_path.file_name().unwrap().to_string_lossy();
+        let build = format!("{}\n//# sourceMappingURL={map_name}\n", &self.content);
+
+        std::fs::write(out_path, build)?;
+        std::fs::write(&map_path, sm_json)?;
+        Ok(map_path)
+    }
 }
 
 fn build(opt: &BuildOptions) -> anyhow::Result<(Build, Option<PatternSources>)> {
@@ -95,17 +160,36 @@ fn build(opt: &BuildOptions) -> anyhow::Result<(Build, Option<PatternSources>)>
         }
     };
 
+    // sources of shared prelude are skipped by plain bundles (pattern builds are opened
+    // out of proxy workspace, so they inline default document as before)
+    let shared_prelude = match opt.resolve_deps && opt.pat.is_none() && opt.pat_sources.is_none() {
+        true => opt.st.get_shared_prelude_for(opt.uri),
+        false => None,
+    };
+
     if opt.resolve_deps {
         initial_buf.push_str("/** DO NOT EDIT THIS FILE. Build of '");
         initial_buf.push_str(&doc.source);
         initial_buf.push_str("' with sourcemaps ");
         initial_buf.push_str("https://evanw.github.io/source-map-visualization/ ");
-        initial_buf.push_str("by glscript-language-server */\n");
+        initial_buf.push_str("by glscript-language-server */");
+        if let Some((prelude, _)) = &shared_prelude {
+            let prelude_path = prelude.uri.to_file_path().unwrap_or_default();
+            let prelude_name = prelude_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy();
+            initial_buf.push_str(&format!(" /// <reference path=\"./{prelude_name}\" />"));
+        }
+        initial_buf.push('\n');
     }
 
     let default_doc = &opt.st.get_default_doc();
     let new_ctx = || {
-        let visited = HashSet::<SourceHash>::with_capacity(sources_cap);
+        let mut visited = HashSet::<SourceHash>::with_capacity(sources_cap);
+        if let Some((_, prelude_sources)) = &shared_prelude {
+            visited.extend(prelude_sources.iter().copied());
+        }
         let (p, s, i) = (opt.pat.clone(), opt.pat_sources.clone(), opt.resolve_deps);
         Context::new(opt.st, default_doc, visited, p, s, i, false, vec![])
     };
@@ -115,11 +199,11 @@ fn build(opt: &BuildOptions) -> anyhow::Result<(Build, Option<PatternSources>)>
         let dst_line = if opt.resolve_deps { 1 } else { 0 };
         let swis = HashMap::with_capacity(sources_cap);
         let mut emit_sourcemap_state =
-            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, swis);
+            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, swis, vec![]);
         Emit::sourcemap(&mut emit_sourcemap_state, &mut new_ctx(), opt.uri);
         match emit_sourcemap_state.finish(opt.st) {
-            EmitResult::TokensCountAndSourceMap(count, sm, sources_stack) => {
-                (count, sm, sources_stack)
+            EmitResult::TokensCountAndSourceMap(count, sm, sources_stack, include_issues) => {
+                (count, sm, sources_stack, include_issues)
             }
             _ => unreachable!(),
         }
@@ -148,7 +232,7 @@ fn build(opt: &BuildOptions) -> anyhow::Result<(Build, Option<PatternSources>)>
         }
     };
 
-    let (tokens_count, source_map, sources_stack) = sourcemap_task;
+    let (tokens_count, source_map, sources_stack, include_issues) = sourcemap_task;
     let (content, pattern_sources) = content_task;
 
     #[cfg(debug_assertions)]
@@ -159,7 +243,16 @@ fn build(opt: &BuildOptions) -> anyhow::Result<(Build, Option<PatternSources>)>
         false => doc.transpile_uri.as_ref().clone(),
     };
 
-    let build = Build::new(content, emit_uri, sources_stack, source_map, tokens_count);
+    let src_index = SourceTokensIndex::new(&source_map);
+    let build = Build::new(
+        content,
+        emit_uri,
+        sources_stack,
+        include_issues,
+        source_map,
+        src_index,
+        tokens_count,
+    );
 
     Ok((build, pattern_sources))
 }
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+
+use async_lsp::lsp_types::Url as Uri;
+use serde::{Deserialize, Serialize};
+
+use crate::builder::emit::Stack;
+use crate::builder::{Build, IncludeIssue, SourceTokensIndex};
+use crate::types::Source;
+
+/// [`Build`] in serializable form (sourcemap is stored as json)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BuildCache {
+    content: String,
+    uri: Uri,
+    sources_stack: HashMap<Source, Stack>,
+    include_issues: Vec<IncludeIssue>,
+    source_map: String,
+    tokens_count: usize,
+}
+
+impl BuildCache {
+    pub fn new(build: &Build) -> anyhow::Result<Self> {
+        let mut source_map = Vec::new();
+        build.source_map.to_writer(&mut source_map)?;
+
+        Ok(Self {
+            content: build.content.clone(),
+            uri: build.uri.clone(),
+            sources_stack: build.sources_stack.clone(),
+            include_issues: build.include_issues.clone(),
+            source_map: String::from_utf8(source_map)?,
+            tokens_count: build.tokens_count,
+        })
+    }
+
+    pub fn restore(self) -> anyhow::Result<Build> {
+        let source_map = sourcemap::SourceMap::from_slice(self.source_map.as_bytes())?;
+        let src_index = SourceTokensIndex::new(&source_map);
+
+        Ok(Build::new(
+            self.content,
+            self.uri,
+            self.sources_stack,
+            self.include_issues,
+            source_map,
+            src_index,
+            self.tokens_count,
+        ))
+    }
+}
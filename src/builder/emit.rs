@@ -2,8 +2,8 @@ use async_lsp::lsp_types::Url as Uri;
 use derive_more::Constructor;
 use std::collections::{HashMap, HashSet};
 
-use crate::builder::PatternSources;
 use crate::builder::source_map_builder::SourceMapBuilder;
+use crate::builder::{IncludeIssue, PatternSources};
 use crate::parser::LineCol;
 use crate::state::State;
 use crate::types::{Source, SourceHash, SourcePattern};
@@ -11,8 +11,11 @@ use crate::types::{Source, SourceHash, SourcePattern};
 mod content;
 mod dev;
 mod prepare;
+mod segment;
 mod source_map;
 
+pub use segment::{ContentSegment, SourceMapSegment};
+
 #[cfg(debug_assertions)]
 pub use dev::emit_on_disk;
 
@@ -29,8 +32,21 @@ pub struct Context<'a> {
     stack: Stack,
 }
 
+impl Context<'_> {
+    /// segments are cached for plain bundles only: pattern builds emit sources partially
+    /// and transpile builds don't resolve dependencies
+    fn is_segment_cacheable(&self) -> bool {
+        self.resolve_deps && self.pat.is_none() && self.pat_sources.is_none()
+    }
+}
+
 pub enum Emit {
-    WithSourceMapBuilderAndDstLine(SourceMapBuilder, u32, HashMap<Source, Stack>),
+    WithSourceMapBuilderAndDstLine(
+        SourceMapBuilder,
+        u32,
+        HashMap<Source, Stack>,
+        Vec<IncludeIssue>,
+    ),
     WithDstContent(String, Option<PatternSources>),
 }
 
@@ -38,8 +54,14 @@ pub enum Emit {
 /// literal length where source were been included by prev source
 pub type Stack = Vec<(Source, LineCol, usize)>;
 
+#[allow(clippy::large_enum_variant)]
 pub enum EmitResult {
-    TokensCountAndSourceMap(usize, sourcemap::SourceMap, HashMap<Source, Stack>),
+    TokensCountAndSourceMap(
+        usize,
+        sourcemap::SourceMap,
+        HashMap<Source, Stack>,
+        Vec<IncludeIssue>,
+    ),
     Content(String, Option<PatternSources>),
 }
 
@@ -49,13 +71,35 @@ impl Emit {
             Emit::WithDstContent(dst_content, pattern_sources) => {
                 EmitResult::Content(dst_content, pattern_sources)
             }
-            Emit::WithSourceMapBuilderAndDstLine(builder, _, sources_stack) => {
+            Emit::WithSourceMapBuilderAndDstLine(builder, _, sources_stack, include_issues) => {
                 EmitResult::TokensCountAndSourceMap(
                     builder.tokens.len(),
                     builder.into_sourcemap(state),
                     sources_stack,
+                    include_issues,
                 )
             }
         }
     }
 }
+
+/// emitted before identifier of interpolation reference: unlike `void` it doesn't throw for
+/// undeclared identifier at runtime (see [`crate::types::InterpolationReference`])
+const REFERENCE_PREFIX: &str = "typeof ";
+
+/// char offsets of region text which are escaped by backslash to keep text of template
+/// literal verbatim: backslashes, backticks and `$` of `${`
+pub fn template_escapes(text: &str) -> impl Iterator<Item = u32> + '_ {
+    let mut chars = text.chars().peekable();
+    let mut offset = 0u32;
+
+    std::iter::from_fn(move || {
+        while let Some(c) = chars.next() {
+            offset += 1;
+            if matches!(c, '\\' | '`') || (c == '$' && chars.peek() == Some(&'{')) {
+                return Some(offset - 1);
+            }
+        }
+        None
+    })
+}
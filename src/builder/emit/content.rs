@@ -1,8 +1,10 @@
+use std::borrow::Cow;
+
 use async_lsp::lsp_types::Url as Uri;
 
-use crate::builder::emit::{Context, Emit};
-use crate::parser::Token;
-use crate::types::{DocumentLinkStatement, SourceHash};
+use crate::builder::emit::{ContentSegment, Context, Emit, REFERENCE_PREFIX, template_escapes};
+use crate::parser::{StringLiteral, Token};
+use crate::types::{Document, DocumentLinkStatement, InterpolationReference, SourceHash};
 
 #[derive(Default)]
 pub struct PatternMatched {
@@ -43,94 +45,228 @@ impl Emit {
             literal: p.source == d.source_hash,
         });
 
-        if ctx.resolve_deps {
-            if let Some(dep_match) = Emit::_content(st, ctx, ctx.default_document) {
-                let current_matched = matched.as_ref().unwrap();
-                matched = Some(PatternMatched {
-                    literal: current_matched.literal.max(dep_match.literal),
-                    source: current_matched.source.max(dep_match.source),
-                });
+        if ctx.resolve_deps
+            && let Some(dep_match) = Emit::_content(st, ctx, ctx.default_document)
+        {
+            let current_matched = matched.as_ref().unwrap();
+            matched = Some(PatternMatched {
+                literal: current_matched.literal.max(dep_match.literal),
+                source: current_matched.source.max(dep_match.source),
+            });
+        }
+
+        let segment = ctx.is_segment_cacheable();
+        let segment = segment.then(|| ctx.proxy_state.get_content_segment(&d));
+        match segment.flatten() {
+            Some(segment) => {
+                for (chunk, idx) in segment.chunks.iter().zip(segment.includes.iter()) {
+                    st.push_str(chunk);
+                    let Token::IncludePath(t) = &d.parse.compressed_tokens[*idx] else {
+                        unreachable!("segment of the same document version");
+                    };
+                    Emit::include_content(st, ctx, &d, t, &mut matched);
+                }
+                st.push_str(segment.chunks.last().unwrap());
             }
+            None => Emit::tokens_content(st, ctx, &d, &mut matched),
+        }
+
+        if ctx.is_default_context {
+            if matches!(matched.as_ref().map(|m| m.literal && m.source), Some(true)) {
+                st.push_pattern_source(d.source_hash);
+            }
+        } else if matches!(matched.as_ref().map(|m| m.literal), Some(true)) {
+            st.push_pattern_source(d.source_hash);
+        }
+
+        if target == ctx.default_document {
+            ctx.is_default_context = false;
+        }
+
+        matched
+    }
+
+    /// emits document tokens and caches emitted chunks around include statements
+    fn tokens_content(
+        st: &mut Emit,
+        ctx: &mut Context,
+        d: &Document,
+        matched: &mut Option<PatternMatched>,
+    ) {
+        let cacheable = ctx.is_segment_cacheable();
+        let (mut chunks, mut includes) = (vec![], vec![]);
+        let mut chunk_start = st.content_len();
 
+        if ctx.resolve_deps {
             st.push_str(&d.decl_stmt);
         }
 
+        let conditionals = ctx.proxy_state.get_conditionals(d);
+        let references = ctx
+            .proxy_state
+            .get_interpolation_references(d, ctx.resolve_deps);
         let mut lt_ro_skip = false;
-        for t in d.parse.compressed_tokens.iter() {
+        let mut in_region = false;
+        for (idx, t) in d.parse.compressed_tokens.iter().enumerate() {
+            // directives and inactive branches keep their width for exact mappings
+            if conditionals.is_inactive(idx) {
+                st.push_blank(t);
+                continue;
+            }
+
             match t {
                 Token::Include(t) => match ctx.resolve_deps {
                     true => (0..t.len).for_each(|_| st.push(' ')),
                     false => st.push_str(&format!("{: <len$}", "import", len = t.len as usize)),
                 },
+                Token::IncludePath(t) if !ctx.resolve_deps => {
+                    st.push('"');
+                    st.push_str(t.lit);
+                    st.push('"');
+                }
                 Token::IncludePath(t) => {
-                    if !ctx.resolve_deps {
-                        st.push('"');
-                        st.push_str(t.lit);
-                        st.push('"');
-                        continue;
+                    if cacheable {
+                        chunks.push(st.content_from(chunk_start));
+                        includes.push(idx);
                     }
 
-                    let dep_path = ctx.proxy_state.path_resolver(&d.path, t.lit);
-                    let dep_uri = || ctx.proxy_state.path_to_uri(&dep_path);
-                    let dep_doc = dep_uri().and_then(|uri| ctx.proxy_state.get_doc(&uri));
-                    let link = dep_doc.as_ref().map(|d| d.link_stmt.as_str());
-                    let dep_exists_and_not_visited = dep_doc
-                        .as_ref()
-                        .map(|d| ctx.visited_sources.contains(&d.source_hash))
-                        .is_ok_and(|visited| !visited);
-
-                    st.push_str(link.unwrap_or(&DocumentLinkStatement::undefined()));
-
-                    if dep_exists_and_not_visited
-                        && let Some(dep_match) = Emit::_content(st, ctx, &dep_uri().unwrap())
-                    {
-                        let current_matched = matched.as_ref().unwrap();
-                        matched = Some(PatternMatched {
-                            literal: current_matched.literal.max(dep_match.literal),
-                            source: current_matched.source.max(dep_match.source),
-                        });
-                    }
-
-                    st.push('\n'); // traling statements after include path on current line
-                    (0..(t.line_col.col + t.lit.len() as u32 + 2)).for_each(|_| st.push(' '));
+                    Emit::include_content(st, ctx, d, t, matched);
+                    chunk_start = st.content_len();
                 }
                 Token::RegionOpen(t) => {
                     lt_ro_skip = true;
-                    (0..(t.len - 1)).for_each(|_| st.push(' '));
+                    in_region = true;
+                    let region = ctx.proxy_state.get_region_kind(d, t.line_col.line);
+                    let prefix = region.map_or("", |r| r.prefix.as_str());
+                    let blank = (t.len - 1).saturating_sub(prefix.chars().count() as u32);
+                    (0..blank).for_each(|_| st.push(' '));
+                    st.push_str(prefix);
                     st.push('`');
                 }
                 Token::LineTerminator(_) if lt_ro_skip => {
                     lt_ro_skip = false;
                 }
                 Token::RegionClose(t) => {
+                    in_region = false;
+                    let region = ctx.proxy_state.get_region_kind(d, t.line_col.line);
+                    let suffix = region.map_or("", |r| r.suffix.as_str());
                     st.push('`');
+                    st.push_str(suffix);
                     st.push(';');
-                    (0..(t.len - 2)).for_each(|_| st.push(' '));
+                    let blank = (t.len - 2).saturating_sub(suffix.chars().count() as u32);
+                    (0..blank).for_each(|_| st.push(' '));
+                }
+                Token::LineTerminator(lc) => {
+                    st.push_references(&references, lc.line);
+                    st.push('\n');
+                }
+                Token::Directive(_) => unreachable!("directives are inactive"),
+                Token::CommonWithLineEnding(t) | Token::Common(t) if in_region => {
+                    st.traverse_common(ctx, matched, &escape_template(t.text))
                 }
-                Token::LineTerminator(_) => st.push('\n'),
-                Token::CommonWithLineEnding(t) => st.traverse_common(ctx, &mut matched, t.text),
-                Token::Common(t) => st.traverse_common(ctx, &mut matched, t.text),
-                Token::Eoi(_) => {}
+                Token::CommonWithLineEnding(t) => {
+                    let text = t.text.trim_end_matches(['\r', '\n']);
+                    st.traverse_common(ctx, matched, text);
+                    st.push_references(&references, t.line_col.line);
+                    st.push_str(&t.text[text.len()..]);
+                }
+                Token::Common(t) => st.traverse_common(ctx, matched, t.text),
+                Token::Eoi(lc) => st.push_references(&references, lc.line),
             }
         }
 
-        if ctx.is_default_context {
-            if matches!(matched.as_ref().map(|m| m.literal && m.source), Some(true)) {
-                st.push_pattern_source(d.source_hash);
-            }
-        } else if matches!(matched.as_ref().map(|m| m.literal), Some(true)) {
-            st.push_pattern_source(d.source_hash);
+        if cacheable {
+            chunks.push(st.content_from(chunk_start));
+            let segment = ContentSegment::new(d.version, chunks, includes);
+            ctx.proxy_state.set_content_segment(d.source_hash, segment);
         }
+    }
 
-        if target == ctx.default_document {
-            ctx.is_default_context = false;
+    /// link statements of dependencies (files matched by glob pattern or single resolved path)
+    /// each followed by dependency content if it is not visited yet
+    fn include_content(
+        st: &mut Emit,
+        ctx: &mut Context,
+        d: &Document,
+        t: &StringLiteral,
+        matched: &mut Option<PatternMatched>,
+    ) {
+        let dep_paths = ctx.proxy_state.resolve_include_paths(&d.path, t.lit);
+
+        for dep_path in dep_paths.iter() {
+            let dep_uri = || ctx.proxy_state.path_to_uri(dep_path);
+            let dep_doc = dep_uri().and_then(|uri| ctx.proxy_state.get_doc(&uri));
+            let link = dep_doc.as_ref().map(|d| d.link_stmt.as_str());
+            let dep_exists_and_not_visited = dep_doc
+                .as_ref()
+                .map(|d| ctx.visited_sources.contains(&d.source_hash))
+                .is_ok_and(|visited| !visited);
+
+            st.push_str(link.unwrap_or(&DocumentLinkStatement::undefined()));
+
+            if dep_exists_and_not_visited
+                && let Some(dep_match) = Emit::_content(st, ctx, &dep_uri().unwrap())
+            {
+                let current_matched = matched.as_ref().unwrap();
+                *matched = Some(PatternMatched {
+                    literal: current_matched.literal.max(dep_match.literal),
+                    source: current_matched.source.max(dep_match.source),
+                });
+            }
         }
 
-        matched
+        st.push('\n'); // traling statements after include path on current line
+        (0..(t.line_col.col + t.lit.len() as u32 + 2)).for_each(|_| st.push(' '));
     }
 }
 
+/// region text as content of template literal
+fn escape_template(text: &str) -> Cow<'_, str> {
+    let mut escapes = template_escapes(text).peekable();
+    if escapes.peek().is_none() {
+        return Cow::Borrowed(text);
+    }
+
+    let mut escaped = String::with_capacity(text.len() + 1);
+    for (offset, c) in text.chars().enumerate() {
+        if escapes.next_if_eq(&(offset as u32)).is_some() {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    Cow::Owned(escaped)
+}
+
 impl Emit {
+    /// whitespace of the same width as inactive `token` (line endings are kept)
+    fn push_blank(&mut self, token: &Token) {
+        let blank = |st: &mut Emit, len: u32| (0..len).for_each(|_| st.push(' '));
+        let blank_text = |st: &mut Emit, text: &str| {
+            for c in text.chars() {
+                st.push(if matches!(c, '\r' | '\n') { c } else { ' ' });
+            }
+        };
+
+        match token {
+            Token::Include(t) | Token::RegionOpen(t) | Token::RegionClose(t) => blank(self, t.len),
+            Token::IncludePath(t) => blank(self, t.lit.chars().count() as u32 + 2),
+            Token::LineTerminator(_) => self.push('\n'),
+            Token::Directive(t) | Token::Common(t) | Token::CommonWithLineEnding(t) => {
+                blank_text(self, t.text)
+            }
+            Token::Eoi(_) => {}
+        }
+    }
+
+    /// references of interpolations after the end of `line`
+    fn push_references(&mut self, references: &[InterpolationReference], line: u32) {
+        for r in references.iter().filter(|r| r.line == line) {
+            self.push_str(REFERENCE_PREFIX);
+            self.push_str(&r.name);
+            self.push(';');
+        }
+    }
+
     #[inline]
     fn push_str(&mut self, str: &str) {
         match self {
@@ -139,6 +275,22 @@ impl Emit {
         }
     }
 
+    #[inline]
+    fn content_len(&self) -> usize {
+        match self {
+            Emit::WithDstContent(dst_content, _) => dst_content.len(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    fn content_from(&self, start: usize) -> String {
+        match self {
+            Emit::WithDstContent(dst_content, _) => dst_content[start..].to_string(),
+            _ => unreachable!(),
+        }
+    }
+
     #[inline]
     fn push_pattern_source(&mut self, source: SourceHash) {
         match self {
@@ -173,3 +325,103 @@ impl Emit {
         self.push_str(t)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use super::*;
+    use crate::state::testing::TestProject;
+    use crate::types::Source;
+
+    /// backtick, `${` and trailing backslash
+    const REGION_LINE: &str = "  a`b ${c} d\\";
+    const ESCAPED_LINE: &str = "  a\\`b \\${c} d\\\\";
+
+    #[test]
+    fn template_special_chars_are_escaped() {
+        assert_eq!(
+            template_escapes(REGION_LINE).collect::<Vec<_>>(),
+            [3, 6, 12]
+        );
+        assert_eq!(escape_template(REGION_LINE), ESCAPED_LINE);
+        assert!(matches!(escape_template("$c {d}"), Cow::Borrowed(_)));
+    }
+
+    fn assert_region_line_round_trip(kind: &str) {
+        let script = format!("var r = #{kind}\n{REGION_LINE}\n#end{kind};\n");
+        let project = TestProject::new(&[("main.js", &script)], json!({}));
+        let source = Source::new("main.js".into());
+
+        for build in [project.transpile("main.js"), project.bundle("main.js")] {
+            let line = |l: u32| build.content.lines().nth(l as usize).unwrap().to_string();
+
+            for (col, c) in REGION_LINE.chars().enumerate() {
+                let pos = lsp::Position::new(1, col as u32);
+                let build_pos = build.forward_src_position(&pos, &source).unwrap();
+                let build_line = line(build_pos.line);
+                assert!(build_line.ends_with(ESCAPED_LINE), "{kind}: {build_line}");
+                assert_eq!(
+                    build_line.chars().nth(build_pos.character as usize),
+                    Some(c)
+                );
+
+                let (back, back_source) = build.forward_build_position(&build_pos).unwrap();
+                assert_eq!((back, back_source), (pos, source.clone()), "{kind} {col}");
+            }
+        }
+    }
+
+    #[test]
+    fn escaped_text_region_is_mapped_to_source_columns() {
+        assert_region_line_round_trip("text");
+    }
+
+    #[test]
+    fn escaped_sql_region_is_mapped_to_source_columns() {
+        assert_region_line_round_trip("sql");
+    }
+
+    #[test]
+    fn only_active_branch_is_emitted_with_exact_mappings() {
+        let script = "#ifdef A\nvar a = 1;\n#else\nvar b = 2;\n#endif\n\
+            #ifdef B\nvar c = 3;\n#else\nvar d = 4;\n#endif\nvar e = 5;\n";
+        let project = TestProject::new(&[("main.js", script)], json!({ "defines": ["A"] }));
+        let source = Source::new("main.js".into());
+        let active = [1, 8, 10];
+
+        for build in [project.transpile("main.js"), project.bundle("main.js")] {
+            for (l, src_line) in script.lines().enumerate() {
+                let l = l as u32;
+                let build_pos = build.forward_src_position(&lsp::Position::new(l, 0), &source);
+                let build_pos = build_pos.unwrap();
+                let build_line = build.content.lines().nth(build_pos.line as usize).unwrap();
+
+                if !active.contains(&l) {
+                    // directives and inactive lines keep their width as blanks
+                    let blank = &build_line[build_pos.character as usize..];
+                    assert_eq!(blank, " ".repeat(src_line.len()), "{l}");
+                    continue;
+                }
+
+                assert!(build_line.ends_with(src_line), "{build_line}");
+                for (col, c) in src_line.chars().enumerate() {
+                    let pos = lsp::Position::new(l, col as u32);
+                    let build_pos = build.forward_src_position(&pos, &source).unwrap();
+                    assert_eq!(
+                        build_line.chars().nth(build_pos.character as usize),
+                        Some(c)
+                    );
+
+                    let (back, back_source) = build.forward_build_position(&build_pos).unwrap();
+                    assert_eq!((back, back_source), (pos, source.clone()), "{l} {col}");
+                }
+            }
+
+            for inactive in ["#ifdef", "#else", "#endif", "var b", "var c"] {
+                assert!(!build.content.contains(inactive), "{inactive}");
+            }
+        }
+    }
+}
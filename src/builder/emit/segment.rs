@@ -0,0 +1,81 @@
+use derive_more::Constructor;
+
+/// Emitted output of single source around its include statements. Include statements are
+/// emitted on every build, because resolving of dependency depends on visited sources
+#[derive(Debug, Constructor)]
+pub struct EmitSegment<T> {
+    /// [`crate::types::Document`] version which segment was emitted from
+    pub version: u32,
+    /// `includes.len() + 1` chunks surrounding include statements
+    pub chunks: Vec<T>,
+    /// indexes of [`crate::parser::Token::IncludePath`] in compressed tokens of document
+    pub includes: Vec<usize>,
+}
+
+#[derive(Debug, Constructor)]
+pub struct TokensChunk {
+    /// tokens with `dst_line` relative to the chunk start
+    pub tokens: Vec<sourcemap::RawToken>,
+    pub lines: u32,
+}
+
+pub type ContentSegment = EmitSegment<String>;
+pub type SourceMapSegment = EmitSegment<TokensChunk>;
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use crate::builder::Build;
+    use crate::state::testing::TestProject;
+
+    fn source_map_json(build: &Build) -> String {
+        let mut json = Vec::new();
+        build.source_map.to_writer(&mut json).unwrap();
+        String::from_utf8(json).unwrap()
+    }
+
+    #[test]
+    fn spliced_bundle_is_equal_to_cold_build() {
+        let files = [
+            (
+                "main.js",
+                "var m = 1;\n#include <a.js>\nvar n = `x\n${m}`;\n#include <b.js>\n\
+                var r = #text\n  %m\n#endtext;\n",
+            ),
+            ("a.js", "var a = 1;\n#include <c.js>\nvar a2 = `%a`;\n"),
+            ("b.js", "var b = 1;\n"),
+            ("c.js", "var c = 1;\n"),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        let st = &project.state;
+        let main = st.get_doc(&project.uri("main.js")).unwrap();
+        project.bundle("main.js");
+
+        // lines are inserted before and after include of nested source
+        let a = project.uri("a.js");
+        let change = |line, character, text: &str| lsp::TextDocumentContentChangeEvent {
+            range: Some(lsp::Range::new(
+                lsp::Position::new(line, character),
+                lsp::Position::new(line, character),
+            )),
+            range_length: None,
+            text: text.into(),
+        };
+        let changes = [change(0, 0, "var x = [\n  1,\n];\n"), change(5, 8, "\n  ")];
+        st.set_doc(&a, &changes).unwrap();
+        assert!(st.get_content_segment(&main).is_some());
+        assert!(st.get_sourcemap_segment(&main).is_some());
+        assert!(st.get_content_segment(&st.get_doc(&a).unwrap()).is_none());
+
+        let spliced = project.bundle("main.js");
+        st.clear_emit_segments();
+        let cold = project.bundle("main.js");
+
+        assert!(spliced.content.contains("var x = [\n  1,\n];\nvar a = 1;"));
+        assert_eq!(spliced.content, cold.content);
+        assert_eq!(source_map_json(&spliced), source_map_json(&cold));
+        assert_eq!(spliced.sources_stack, cold.sources_stack);
+    }
+}
@@ -1,9 +1,12 @@
 use async_lsp::lsp_types::Url as Uri;
 use std::sync::Arc;
 
-use crate::builder::emit::{Context, Emit};
-use crate::parser::{LineCol, Token};
-use crate::types::{DocumentLinkStatement, Source};
+use crate::builder::emit::segment::TokensChunk;
+use crate::builder::emit::{Context, Emit, REFERENCE_PREFIX, SourceMapSegment, template_escapes};
+use crate::builder::{IncludeIssue, IncludeIssueKind};
+use crate::parser::{LineCol, StringLiteral, Token};
+use crate::state::is_glob;
+use crate::types::{Document, DocumentLinkStatement, Source};
 
 /// SourceMap
 impl Emit {
@@ -46,7 +49,32 @@ impl Emit {
                 Emit::_sourcemap(st, ctx, ctx.default_document);
                 ctx.stack.pop();
             }
+        }
+
+        let segment = ctx.is_segment_cacheable();
+        let segment = segment.then(|| ctx.proxy_state.get_sourcemap_segment(&d));
+        match segment.flatten() {
+            Some(segment) => {
+                for (chunk, idx) in segment.chunks.iter().zip(segment.includes.iter()) {
+                    st.add_tokens_chunk(chunk, src_id);
+                    let Token::IncludePath(t) = &d.parse.compressed_tokens[*idx] else {
+                        unreachable!("segment of the same document version");
+                    };
+                    Emit::include_sourcemap(st, ctx, &d, t, src_id);
+                }
+                st.add_tokens_chunk(segment.chunks.last().unwrap(), src_id);
+            }
+            None => Emit::tokens_sourcemap(st, ctx, &d, src_id),
+        }
+    }
+
+    /// emits document tokens and caches emitted chunks around include statements
+    fn tokens_sourcemap(st: &mut Emit, ctx: &mut Context, d: &Document, src_id: u32) {
+        let cacheable = ctx.is_segment_cacheable();
+        let (mut chunks, mut includes) = (vec![], vec![]);
+        let mut chunk_start = st.tokens_position();
 
+        if ctx.resolve_deps {
             // DocumentDeclarationStatement
             st.line_break();
             st.add_token(0, 0, 0, src_id);
@@ -56,6 +84,9 @@ impl Emit {
         let mut lt_ro_skip = false;
         let mut lt_ro = false;
         let mut lt_ro_offset = 0u32;
+        let mut in_region = false;
+        let mut escapes_offset = 0u32; // backslashes emitted before on current line of region
+        let mut close_widening = 0u32; // suffix of region kind which doesn't fit in statement
         let add_map =
             |dst_col: u32, pos: &LineCol, st: &mut Emit, lt_ro: bool, lt_ro_offset: u32| {
                 let dst_col = match lt_ro {
@@ -65,88 +96,205 @@ impl Emit {
                 st.add_token(dst_col, pos.line, pos.col, src_id);
             };
 
-        for t in d.parse.compressed_tokens.iter() {
-            match t {
-                Token::Include(t) => add_map(t.line_col.col, &t.line_col, st, lt_ro, lt_ro_offset),
-                Token::IncludePath(t) => {
-                    let dep_path = ctx.proxy_state.path_resolver(&d.path, t.lit);
-                    let dep_uri = || ctx.proxy_state.path_to_uri(&dep_path);
-                    let dep_doc = dep_uri().and_then(|uri| ctx.proxy_state.get_doc(&uri));
-                    let dep_exists_and_not_visited = dep_doc
-                        .as_ref()
-                        .map(|d| ctx.visited_sources.contains(&d.source_hash))
-                        .is_ok_and(|visited| !visited);
-
-                    if let Ok(doc) = dep_doc.as_ref() {
-                        let source = doc.source.clone();
-                        let source = (*source).clone();
-                        let stack = (source.clone(), t.line_col.clone(), t.lit.len());
-                        ctx.stack.push(stack);
-
-                        // 1* - emit stack for transpile builds like bundle
-                        if !ctx.resolve_deps {
-                            st.add_source_stack(source, ctx);
-                            ctx.stack.pop();
-                        }
-                    }
+        let conditionals = ctx.proxy_state.get_conditionals(d);
+        let references = ctx
+            .proxy_state
+            .get_interpolation_references(d, ctx.resolve_deps);
 
-                    if !ctx.resolve_deps {
-                        let real_col = t.line_col.col - 1;
-                        let pos = &((t.line_col.line, real_col).into());
-                        add_map(real_col, pos, st, lt_ro, lt_ro_offset);
-
-                        continue;
-                    }
-
-                    let link = dep_doc.as_ref().map(|d| d.link_stmt.clone());
-                    let link = link.unwrap_or(DocumentLinkStatement::undefined().into());
+        // identifier of each reference after the end of line is mapped to its interpolation
+        let add_references =
+            |mut dst_col: u32, line: u32, st: &mut Emit, lt_ro: bool, lt_ro_offset: u32| {
+                if lt_ro {
+                    dst_col += lt_ro_offset;
+                }
+                for r in references.iter().filter(|r| r.line == line) {
+                    dst_col += REFERENCE_PREFIX.len() as u32;
+                    st.add_token(dst_col, r.range.start.line, r.range.start.character, src_id);
+                    dst_col += r.name.chars().count() as u32 + 1;
+                    st.add_token(dst_col, 0, 0, !0);
+                }
+            };
 
+        for (idx, t) in d.parse.compressed_tokens.iter().enumerate() {
+            // blank of inactive token is mapped to its start
+            if conditionals.is_inactive(idx) {
+                let lc = t.line_col();
+                add_map(lc.col, lc, st, lt_ro, lt_ro_offset);
+                if matches!(t, Token::LineTerminator(_) | Token::CommonWithLineEnding(_)) {
+                    lt_ro = false;
                     st.line_break();
-                    st.add_token(link.left_offset, t.line_col.line, t.line_col.col, src_id);
-                    st.add_token(link.right_offset, 0, 0, !0);
-                    st.line_break();
+                }
+                continue;
+            }
 
-                    // 1*
-                    if dep_exists_and_not_visited {
-                        Emit::_sourcemap(st, ctx, &dep_uri().unwrap());
-                    }
+            match t {
+                Token::Include(t) => add_map(t.line_col.col, &t.line_col, st, lt_ro, lt_ro_offset),
+                Token::IncludePath(t) if !ctx.resolve_deps => {
+                    Emit::include_sourcemap(st, ctx, d, t, src_id);
 
-                    if dep_doc.is_ok() {
-                        ctx.stack.pop();
+                    let real_col = t.line_col.col - 1;
+                    let pos = &((t.line_col.line, real_col).into());
+                    add_map(real_col, pos, st, lt_ro, lt_ro_offset);
+                }
+                Token::IncludePath(t) => {
+                    if cacheable {
+                        chunks.push(st.tokens_chunk_from(chunk_start));
+                        includes.push(idx);
                     }
 
-                    st.line_break(); // traling statements after include path on current line
+                    Emit::include_sourcemap(st, ctx, d, t, src_id);
+                    chunk_start = st.tokens_position();
                 }
                 Token::RegionOpen(t) => {
                     add_map(0, &t.line_col, st, lt_ro, lt_ro_offset);
                     lt_ro_skip = true;
-                    lt_ro_offset = t.len;
+                    // prefix of region kind which doesn't fit in statement widens it
+                    let region = ctx.proxy_state.get_region_kind(d, t.line_col.line);
+                    let prefix_len = region.map_or(0, |r| r.prefix.chars().count() as u32);
+                    lt_ro_offset = t.len.max(prefix_len + 1);
+                    in_region = true;
                 }
                 Token::LineTerminator(_) if lt_ro_skip => {
                     lt_ro_skip = false;
                     lt_ro = true;
                 }
-                Token::RegionClose(t) => add_map(0, &t.line_col, st, lt_ro, lt_ro_offset),
+                Token::RegionClose(t) => {
+                    add_map(0, &t.line_col, st, lt_ro, lt_ro_offset);
+                    in_region = false;
+                    let region = ctx.proxy_state.get_region_kind(d, t.line_col.line);
+                    let suffix_len = region.map_or(0, |r| r.suffix.chars().count() as u32);
+                    close_widening = (suffix_len + 2).saturating_sub(t.len);
+                }
                 Token::LineTerminator(t) => {
-                    add_map(t.col, t, st, lt_ro, lt_ro_offset);
-                    lt_ro = false;
+                    let end = t.col + escapes_offset + close_widening;
+                    add_map(end, t, st, lt_ro, lt_ro_offset);
+                    add_references(end, t.line, st, lt_ro, lt_ro_offset);
+                    (lt_ro, escapes_offset, close_widening) = (false, 0, 0);
                     st.line_break();
                 }
-                Token::CommonWithLineEnding(t) => {
-                    add_map(t.line_col.col, &t.line_col, st, lt_ro, lt_ro_offset);
-                    lt_ro = false;
-                    st.line_break();
+                Token::CommonWithLineEnding(rt) | Token::Common(rt) => {
+                    let (col, line) = (rt.line_col.col, rt.line_col.line);
+                    add_map(col + escapes_offset, &rt.line_col, st, lt_ro, lt_ro_offset);
+
+                    // text after each escaping backslash is shifted
+                    let escapes = in_region.then(|| template_escapes(rt.text));
+                    let escapes_before = escapes_offset;
+                    for offset in escapes.into_iter().flatten() {
+                        escapes_offset += 1;
+                        let pos = &(line, col + offset).into();
+                        add_map(col + offset + escapes_offset, pos, st, lt_ro, lt_ro_offset);
+                    }
+
+                    // text after identifier of referenced interpolation isn't mapped to reference
+                    let text = rt.text.trim_end_matches(['\r', '\n']);
+                    let text_end = col + text.chars().count() as u32;
+                    let ends = references.iter().map(|r| r.range.end);
+                    let ends =
+                        ends.filter(|e| e.line == line && (col..=text_end).contains(&e.character));
+                    for end in ends {
+                        let escapes = template_escapes(rt.text);
+                        let escapes = escapes.filter(|o| in_region && col + o < end.character);
+                        let dst_col = end.character + escapes_before + escapes.count() as u32;
+                        let pos = &(line, end.character).into();
+                        add_map(dst_col, pos, st, lt_ro, lt_ro_offset);
+                    }
+
+                    if matches!(t, Token::CommonWithLineEnding(_)) {
+                        let end = text_end + escapes_offset;
+                        add_references(end, line, st, lt_ro, lt_ro_offset);
+                        (lt_ro, escapes_offset, close_widening) = (false, 0, 0);
+                        st.line_break();
+                    }
+                }
+                Token::Directive(_) => unreachable!("directives are inactive"),
+                Token::Eoi(t) => {
+                    add_map(t.col, t, st, lt_ro, lt_ro_offset);
+                    let end = t.col + escapes_offset + close_widening;
+                    add_references(end, t.line, st, lt_ro, lt_ro_offset);
+                }
+            }
+        }
+
+        if cacheable {
+            chunks.push(st.tokens_chunk_from(chunk_start));
+            let segment = SourceMapSegment::new(d.version, chunks, includes);
+            ctx.proxy_state
+                .set_sourcemap_segment(d.source_hash, segment);
+        }
+    }
+
+    /// link statements of dependencies (files matched by glob pattern or single resolved path)
+    /// each followed by dependency sourcemap if it is not visited yet
+    fn include_sourcemap(
+        st: &mut Emit,
+        ctx: &mut Context,
+        d: &Document,
+        t: &StringLiteral,
+        src_id: u32,
+    ) {
+        let dep_paths = ctx.proxy_state.resolve_include_paths(&d.path, t.lit);
+        // glob matches files regardless of includes, so visited ones are not issues
+        let is_glob = is_glob(t.lit);
+
+        for dep_path in dep_paths.iter() {
+            let dep_uri = || ctx.proxy_state.path_to_uri(dep_path);
+            let dep_doc = dep_uri().and_then(|uri| ctx.proxy_state.get_doc(&uri));
+            let dep_exists_and_not_visited = dep_doc
+                .as_ref()
+                .map(|d| ctx.visited_sources.contains(&d.source_hash))
+                .is_ok_and(|visited| !visited);
+
+            if let Ok(doc) = dep_doc.as_ref() {
+                let source = doc.source.clone();
+                let source = (*source).clone();
+                let stack = (source.clone(), t.line_col.clone(), t.lit.len());
+                ctx.stack.push(stack);
+
+                // 1* - emit stack for transpile builds like bundle
+                if !ctx.resolve_deps {
+                    st.add_source_stack(source, ctx);
+                    ctx.stack.pop();
                 }
-                Token::Common(t) => add_map(t.line_col.col, &t.line_col, st, lt_ro, lt_ro_offset),
-                Token::Eoi(t) => add_map(t.col, t, st, lt_ro, lt_ro_offset),
             }
+
+            if !ctx.resolve_deps {
+                continue;
+            }
+
+            if let Ok(dep) = dep_doc.as_ref()
+                && !dep_exists_and_not_visited
+                && !is_glob
+            {
+                st.add_include_issue(&d.source, &dep.source, ctx);
+            }
+
+            let link = dep_doc.as_ref().map(|d| d.link_stmt.clone());
+            let link = link.unwrap_or(DocumentLinkStatement::undefined().into());
+
+            st.line_break();
+            st.add_token(link.left_offset, t.line_col.line, t.line_col.col, src_id);
+            st.add_token(link.right_offset, 0, 0, !0);
+            st.line_break();
+
+            // 1*
+            if dep_exists_and_not_visited {
+                Emit::_sourcemap(st, ctx, &dep_uri().unwrap());
+            }
+
+            if dep_doc.is_ok() {
+                ctx.stack.pop();
+            }
+        }
+
+        if ctx.resolve_deps {
+            st.line_break(); // traling statements after include path on current line
         }
     }
 
     #[inline]
     pub fn line_break(&mut self) {
         match self {
-            Emit::WithSourceMapBuilderAndDstLine(_, dst_line, _) => *dst_line += 1,
+            Emit::WithSourceMapBuilderAndDstLine(_, dst_line, _, _) => *dst_line += 1,
             _ => unreachable!(),
         };
     }
@@ -156,7 +304,7 @@ impl Emit {
     /// returns source id of [`crate::builder::SourceMapBuilder`]
     fn add_source(&mut self, source: Arc<Source>) -> u32 {
         match self {
-            Emit::WithSourceMapBuilderAndDstLine(builder, _, _) => {
+            Emit::WithSourceMapBuilderAndDstLine(builder, _, _, _) => {
                 builder.add_source_with_id(source)
             }
             _ => unreachable!(),
@@ -165,7 +313,7 @@ impl Emit {
 
     fn add_source_stack(&mut self, source: Source, ctx: &Context) {
         match self {
-            Emit::WithSourceMapBuilderAndDstLine(_, _, sources_stack) => {
+            Emit::WithSourceMapBuilderAndDstLine(_, _, sources_stack, _) => {
                 if !ctx.stack.is_empty() {
                     let mut ctx_stack = ctx.stack.clone();
 
@@ -185,9 +333,85 @@ impl Emit {
         }
     }
 
+    /// `ctx.stack` should end with include statement of already visited `target`
+    fn add_include_issue(&mut self, includer: &Source, target: &Source, ctx: &Context) {
+        match self {
+            Emit::WithSourceMapBuilderAndDstLine(builder, _, sources_stack, include_issues) => {
+                let includer_stack = &ctx.stack[..ctx.stack.len() - 1];
+                let default_source = (ctx.proxy_state.get_doc(ctx.default_document))
+                    .map(|d| (*d.source).clone())
+                    .ok();
+
+                let kind = match sources_stack.get(target) {
+                    // target is skipped because of shared prelude (see State::get_shared_prelude_for)
+                    None if !builder.contains_source(target) => IncludeIssueKind::Redundant,
+                    // only root source is visited without stack
+                    None => IncludeIssueKind::Cycle,
+                    Some(_) if includer_stack.iter().any(|(s, _, _)| s == target) => {
+                        IncludeIssueKind::Cycle
+                    }
+                    Some(first) => {
+                        let by_default_doc =
+                            first.first().map(|(s, _, _)| s) == default_source.as_ref();
+                        let by_includer = first.len() > includer_stack.len()
+                            && (first.iter().zip(includer_stack)).all(|(a, b)| a.0 == b.0);
+
+                        match by_default_doc || by_includer {
+                            true => IncludeIssueKind::Redundant,
+                            false => return,
+                        }
+                    }
+                };
+
+                let issue =
+                    IncludeIssue::new(kind, includer.clone(), target.clone(), ctx.stack.clone());
+                include_issues.push(issue);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// count of tokens and destination line
+    fn tokens_position(&self) -> (usize, u32) {
+        match self {
+            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, _, _) => {
+                (builder.tokens.len(), *dst_line)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn tokens_chunk_from(&self, (start, start_line): (usize, u32)) -> TokensChunk {
+        match self {
+            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, _, _) => {
+                let tokens = builder.tokens[start..].iter().map(|t| sourcemap::RawToken {
+                    dst_line: t.dst_line - start_line,
+                    ..*t
+                });
+                TokensChunk::new(tokens.collect(), *dst_line - start_line)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn add_tokens_chunk(&mut self, chunk: &TokensChunk, src_id: u32) {
+        match self {
+            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, _, _) => {
+                let tokens = chunk.tokens.iter().map(|t| sourcemap::RawToken {
+                    dst_line: t.dst_line + *dst_line,
+                    src_id: if t.src_id == !0 { !0 } else { src_id },
+                    ..*t
+                });
+                builder.tokens.extend(tokens);
+                *dst_line += chunk.lines;
+            }
+            _ => unreachable!(),
+        }
+    }
+
     fn add_token(&mut self, dst_col: u32, src_line: u32, src_col: u32, src_id: u32) {
         match self {
-            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, _) => {
+            Emit::WithSourceMapBuilderAndDstLine(builder, dst_line, _, _) => {
                 builder.tokens.push(sourcemap::RawToken {
                     dst_line: *dst_line,
                     dst_col,
@@ -1,9 +1,44 @@
 use async_lsp::lsp_types::{self as lsp};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::builder::Build;
 use crate::types::Source;
 
+/// Tokens of each [`Source`] ordered by source position (line, column) for source to build
+/// lookups. Built once after emit, so forwarding doesn't scan all tokens of sourcemap
+#[derive(Debug, Default)]
+pub struct SourceTokensIndex(HashMap<Source, Vec<(u32, u32, u32)>>);
+
+impl SourceTokensIndex {
+    pub fn new(source_map: &sourcemap::SourceMap) -> Self {
+        let mut index: HashMap<Source, Vec<(u32, u32, u32)>> = HashMap::new();
+        let sources = source_map.sources().map(|s| Source::new(s.into()));
+        let sources = sources.collect::<Vec<_>>();
+
+        for (idx, t) in source_map.tokens().enumerate() {
+            let Some(source) = sources.get(t.get_src_id() as usize) else {
+                continue;
+            };
+            let source_tokens = index.entry(source.clone()).or_default();
+            source_tokens.push((t.get_src_line(), t.get_src_col(), idx as u32));
+        }
+
+        // stable sort keeps build order of tokens with the same source position
+        index
+            .values_mut()
+            .for_each(|t| t.sort_by_key(|(l, c, _)| (*l, *c)));
+        Self(index)
+    }
+
+    /// index of last token of `source` at `line` starting before or at `col`
+    fn lookup(&self, source: &Source, line: u32, col: u32) -> Option<u32> {
+        let tokens = self.0.get(source)?;
+        let after = tokens.partition_point(|(l, c, _)| (*l, *c) <= (line, col));
+        let (l, _, idx) = tokens.get(after.checked_sub(1)?)?;
+        (*l == line).then_some(*idx)
+    }
+}
+
 /// Forwarding
 impl Build {
     pub fn sources(&self) -> HashSet<Source> {
@@ -19,25 +54,8 @@ impl Build {
         pos: &lsp::Position,
         pos_source: &Source,
     ) -> Option<lsp::Position> {
-        let mut token: Option<sourcemap::Token> = None;
-
-        if !self.sources().contains(pos_source) {
-            return None;
-        }
-
-        for t in self.source_map.tokens() {
-            if t.get_source() != Some(pos_source) {
-                continue;
-            }
-            if t.get_src_line() == pos.line && t.get_src_col() <= pos.character {
-                token = Some(t);
-            }
-            if t.get_src_line() > pos.line {
-                break;
-            }
-        }
-
-        token.map(|t| {
+        let idx = self.src_index.lookup(pos_source, pos.line, pos.character)?;
+        self.source_map.get_token(idx as usize).map(|t| {
             let line = t.get_dst_line();
             let character = t.get_dst_col() + (pos.character - t.get_src_col());
             lsp::Position::new(line, character)
@@ -88,3 +106,85 @@ impl Build {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use crate::state::testing::TestProject;
+    use crate::types::Source;
+
+    /// `Build::forward_src_position` before [`super::SourceTokensIndex`] (scan of all tokens)
+    fn forward_src_position_by_scan(
+        source_map: &sourcemap::SourceMap,
+        pos: &lsp::Position,
+        pos_source: &Source,
+    ) -> Option<lsp::Position> {
+        let sources = source_map.sources().map(|s| Source::new(s.into()));
+        let sources = sources.collect::<std::collections::HashSet<_>>();
+        let mut token: Option<sourcemap::Token> = None;
+
+        if !sources.contains(pos_source) {
+            return None;
+        }
+
+        for t in source_map.tokens() {
+            if t.get_source() != Some(pos_source) {
+                continue;
+            }
+            if t.get_src_line() == pos.line && t.get_src_col() <= pos.character {
+                token = Some(t);
+            }
+            if t.get_src_line() > pos.line {
+                break;
+            }
+        }
+
+        token.map(|t| {
+            let line = t.get_dst_line();
+            let character = t.get_dst_col() + (pos.character - t.get_src_col());
+            lsp::Position::new(line, character)
+        })
+    }
+
+    /// interpolations of regions are escaped: their identifiers are also mapped to references
+    /// after region, which the scan doesn't reach
+    const SCRIPT: &str = "var a%i = 'x %a%i y';\n\
+        function f%i(b) {\n  return `t ${b}\n  u` + /* c\n  d */ b;\n}\n\
+        var t = #text\n  `%%a%i` ${x}\n#endtext;\n\
+        var q = #sql\n  select %%a%i from t\n#endsql;\n";
+
+    #[test]
+    fn index_lookup_is_equal_to_scan() {
+        let scripts = (0..120)
+            .map(|i| (format!("lib/s{i}.js"), SCRIPT.replace("%i", &i.to_string())))
+            .collect::<Vec<_>>();
+        let main = (scripts.iter())
+            .map(|(path, _)| format!("#include <{path}>\n"))
+            .collect::<String>();
+
+        let mut files = vec![("main.js", main.as_str())];
+        files.extend(scripts.iter().map(|(p, s)| (p.as_str(), s.as_str())));
+        let project = TestProject::new(&files, json!({}));
+        let bundle = project.bundle("main.js");
+        let mut checked = 0;
+
+        for source in bundle.sources() {
+            let Ok(text) = std::fs::read_to_string(project.root.join(source.as_str())) else {
+                continue;
+            };
+
+            checked += 1;
+            for (line, text) in text.lines().enumerate() {
+                for col in 0..text.len() as u32 + 2 {
+                    let pos = lsp::Position::new(line as u32, col);
+                    let expected = forward_src_position_by_scan(&bundle.source_map, &pos, &source);
+                    assert_eq!(bundle.forward_src_position(&pos, &source), expected);
+                }
+            }
+        }
+
+        assert!(checked > scripts.len());
+    }
+}
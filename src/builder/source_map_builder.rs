@@ -33,19 +33,21 @@ impl SourceMapBuilder {
         id
     }
 
+    pub fn contains_source(&self, source: &Source) -> bool {
+        self.source_map.contains_key(source)
+    }
+
     #[allow(unused_mut)]
     pub fn into_sourcemap(mut self, _state: &State) -> sourcemap::SourceMap {
         let contents;
 
         #[cfg(debug_assertions)]
         {
-            let project = _state.get_project();
             if self.sources.len() > self.source_contents.len() {
                 self.source_contents.resize(self.sources.len(), None);
             }
             for (id, source) in self.sources.iter().enumerate() {
-                let path = source.as_str();
-                let doc_uri = _state.path_to_uri(&project.join(path)).unwrap();
+                let doc_uri = _state.path_to_uri(&_state.get_source_path(source)).unwrap();
                 let contents = _state.get_doc(&doc_uri).unwrap().buffer.to_string();
                 self.source_contents[id] = Some(contents.into());
             }
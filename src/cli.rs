@@ -0,0 +1,361 @@
+use std::path::{Path, PathBuf};
+
+use async_lsp::lsp_types::Url as Uri;
+
+use crate::builder::{Build, BuildOptionsBuilder, EMIT_FILE_EXT};
+use crate::proxy::{JS_FILE_EXT, PROXY_WORKSPACE};
+use crate::state::State;
+
+const USAGE: &str = "\
+usage:
+    glscript-language-server <tsserver> [--log-file] [--build-cache] [--shared-prelude]
+    glscript-language-server bundle <entry.js> -o <out.js> [--project <dir>]
+    glscript-language-server transpile <files|dirs|globs>... [--in-place | --out-dir <dir>] [--project <dir>]";
+
+/// Native build commands which run without tsserver. Returns `None` if `args` (without
+/// binary path) are not a command, so they should be treated as proxy arguments
+pub fn run(args: &[String]) -> Option<anyhow::Result<()>> {
+    match args.first().map(String::as_str) {
+        Some("bundle") => Some(bundle(&args[1..])),
+        Some("transpile") => Some(transpile(&args[1..])),
+        Some("help" | "--help" | "-h") => {
+            println!("{USAGE}");
+            Some(Ok(()))
+        }
+        _ => None,
+    }
+}
+
+fn bundle(args: &[String]) -> anyhow::Result<()> {
+    let mut entry = None;
+    let mut out = None;
+    let mut project = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--out" => out = Some(value(arg, args.next())?),
+            "--project" => project = Some(value(arg, args.next())?),
+            a if a.starts_with('-') => anyhow::bail!("unknown option `{a}`\n\n{USAGE}"),
+            _ if entry.is_some() => anyhow::bail!("unexpected argument `{arg}`\n\n{USAGE}"),
+            _ => entry = Some(PathBuf::from(arg)),
+        }
+    }
+
+    let entry = entry.ok_or_else(|| anyhow::anyhow!("expected entry file\n\n{USAGE}"))?;
+    let out = out.ok_or_else(|| anyhow::anyhow!("expected `-o <out.js>`\n\n{USAGE}"))?;
+    let state = init_state(project)?;
+
+    let entry_uri = file_uri(&entry)?;
+    let build = Build::create(BuildOptionsBuilder::init(&entry_uri, &state))?;
+    let map = build.save_with_source_map(&state, &out)?;
+
+    println!(
+        "bundled {} sources into {} ({})",
+        build.sources().len(),
+        out.display(),
+        map.display()
+    );
+    Ok(())
+}
+
+/// Destination of transpiled files
+#[derive(Debug, PartialEq)]
+enum TranspileOutput {
+    /// contents of several files are separated by `// <path>` headers
+    Stdout {
+        headers: bool,
+    },
+    InPlace,
+    OutDir(PathBuf),
+}
+
+/// patterns of files, output and project of `transpile` command
+fn transpile_args(
+    args: &[String],
+) -> anyhow::Result<(Vec<&str>, TranspileOutput, Option<PathBuf>)> {
+    let mut patterns = vec![];
+    let mut output = None;
+    let mut project = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let out = match arg.as_str() {
+            "--in-place" => TranspileOutput::InPlace,
+            "--out-dir" => TranspileOutput::OutDir(value(arg, args.next())?),
+            "--project" => {
+                project = Some(value(arg, args.next())?);
+                continue;
+            }
+            a if a.starts_with('-') => anyhow::bail!("unknown option `{a}`\n\n{USAGE}"),
+            _ => {
+                patterns.push(arg.as_str());
+                continue;
+            }
+        };
+
+        if output.is_some() {
+            anyhow::bail!("expected one of `--in-place` or `--out-dir`\n\n{USAGE}");
+        }
+        output = Some(out);
+    }
+
+    if patterns.is_empty() {
+        anyhow::bail!("expected files to transpile\n\n{USAGE}");
+    }
+
+    let output = output.unwrap_or(TranspileOutput::Stdout { headers: false });
+    Ok((patterns, output, project))
+}
+
+fn transpile(args: &[String]) -> anyhow::Result<()> {
+    let (patterns, mut output, project) = transpile_args(args)?;
+    let state = init_state(project)?;
+    let project = state.get_project().clone();
+    let files = collect_scripts(&patterns, &project)?;
+
+    if let TranspileOutput::Stdout { headers } = &mut output {
+        *headers = files.len() > 1;
+    }
+
+    let mut stdout = std::io::stdout().lock();
+    let (mut changed, mut unchanged, mut failed) = (0, 0, 0);
+    for path in files.iter() {
+        let result = transpile_file(&state, path, &output, &mut stdout);
+        let relative = path.strip_prefix(&project).unwrap_or(path).display();
+
+        match result {
+            Ok(true) => {
+                changed += 1;
+                eprintln!("transpiled {relative}");
+            }
+            Ok(false) => unchanged += 1,
+            Err(err) => {
+                failed += 1;
+                eprintln!("failed {relative}: {err:#}");
+            }
+        }
+    }
+
+    eprintln!(
+        "{} files: {changed} changed, {unchanged} unchanged, {failed} failed",
+        files.len()
+    );
+
+    match failed {
+        0 => Ok(()),
+        _ => Err(anyhow::anyhow!("{failed} files failed to transpile")),
+    }
+}
+
+/// returns true if transpiled content differs from original
+fn transpile_file(
+    state: &State,
+    path: &Path,
+    output: &TranspileOutput,
+    stdout: &mut dyn std::io::Write,
+) -> anyhow::Result<bool> {
+    let uri = file_uri(path)?;
+    let build = Build::create(BuildOptionsBuilder::init(&uri, state).transpile_mode())?;
+    let doc = state.get_doc(&uri)?;
+    let changed = doc.parse_content.replace("\r\n", "\n") != build.content.replace("\r\n", "\n");
+    let relative = || {
+        let relative = path.strip_prefix(state.get_project());
+        relative.map_err(|_| anyhow::anyhow!("out of project"))
+    };
+
+    match output {
+        TranspileOutput::Stdout { headers: false } => write!(stdout, "{}", build.content)?,
+        TranspileOutput::Stdout { headers: true } => {
+            let header = relative()?.to_string_lossy().replace('\\', "/");
+            writeln!(stdout, "// {header}")?;
+            write!(stdout, "{}", build.content)?;
+            if !build.content.ends_with('\n') {
+                writeln!(stdout)?;
+            }
+        }
+        TranspileOutput::InPlace if changed => std::fs::write(path, &build.content)?,
+        TranspileOutput::InPlace => {}
+        TranspileOutput::OutDir(dir) => {
+            let out_path = dir.join(relative()?);
+            std::fs::create_dir_all(out_path.parent().unwrap())?;
+            std::fs::write(out_path, &build.content)?;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// scripts of files, directories (recursively) and glob patterns relative to current dir
+fn collect_scripts(patterns: &[&str], project: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    use ignore::WalkBuilder;
+    use ignore::overrides::OverrideBuilder;
+
+    let cwd = std::env::current_dir()?;
+    let proxy_ws = project.join(PROXY_WORKSPACE);
+    let mut globs = OverrideBuilder::new(&cwd);
+    let mut has_globs = false;
+    let mut roots = vec![];
+
+    for pattern in patterns {
+        let path = cwd.join(pattern);
+        match path.exists() {
+            true => roots.push(path),
+            false => {
+                globs.add(pattern)?;
+                has_globs = true;
+            }
+        }
+    }
+
+    if has_globs {
+        let walk = WalkBuilder::new(&cwd).overrides(globs.build()?).build();
+        let files = walk
+            .flatten()
+            .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()));
+        roots.extend(files.map(|e| e.into_path()));
+    }
+
+    let mut scripts = vec![];
+    for root in roots {
+        for entry in WalkBuilder::new(root).build().flatten() {
+            let path = entry.path();
+            let name = path.to_string_lossy();
+            let is_file = entry.file_type().is_some_and(|ft| ft.is_file());
+            let is_script = name.ends_with(JS_FILE_EXT) && !name.ends_with(EMIT_FILE_EXT);
+
+            if is_file && is_script {
+                let path = dunce::canonicalize(path)?;
+                if !path.starts_with(&proxy_ws) {
+                    scripts.push(path);
+                }
+            }
+        }
+    }
+
+    scripts.sort();
+    scripts.dedup();
+    Ok(scripts)
+}
+
+/// path argument of `option`
+fn value(option: &str, value: Option<&String>) -> anyhow::Result<PathBuf> {
+    let value = value.ok_or_else(|| anyhow::anyhow!("expected value of `{option}`"))?;
+    Ok(PathBuf::from(value))
+}
+
+/// state of project (current dir by default) without client and tsserver
+fn init_state(project: Option<PathBuf>) -> anyhow::Result<State> {
+    let project = project.unwrap_or(std::env::current_dir()?);
+    let state = State::default();
+    state.initialize_project(&file_uri(&project)?, None);
+    state.initialize_include_roots(None);
+    state.initialize_defines(None);
+    state.initialize_region_kinds(None);
+    Ok(state)
+}
+
+fn file_uri(path: &Path) -> anyhow::Result<Uri> {
+    let path = dunce::canonicalize(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    Uri::from_file_path(&path).map_err(|_| anyhow::anyhow!("invalid path {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::proxy::DEFAULT_SCRIPT_FILENAME;
+    use crate::state::testing::TestProject;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn transpile_output_options_are_exclusive() {
+        let stdout_args = args(&["a.js", "b"]);
+        let (patterns, output, _) = transpile_args(&stdout_args).unwrap();
+        assert_eq!(patterns, ["a.js", "b"]);
+        assert_eq!(output, TranspileOutput::Stdout { headers: false });
+
+        let (_, output, _) = transpile_args(&args(&["--out-dir", "out", "a.js"])).unwrap();
+        assert_eq!(output, TranspileOutput::OutDir("out".into()));
+
+        for exclusive in [
+            &["--in-place", "--out-dir", "out", "a.js"][..],
+            &["--out-dir", "out", "a.js", "--in-place"],
+            &["--out-dir", "out", "--out-dir", "out2", "a.js"],
+        ] {
+            let err = transpile_args(&args(exclusive)).unwrap_err();
+            assert!(err.to_string().contains("expected one of"), "{err}");
+        }
+    }
+
+    #[test]
+    fn several_transpiled_files_are_separated_by_headers() {
+        let files = [
+            ("a.js", "#include <lib/b.js>\nvar a;"),
+            ("lib/b.js", "var b;\n"),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        let transpile = |headers: bool, path: &str| {
+            let mut stdout = Vec::new();
+            let output = TranspileOutput::Stdout { headers };
+            let path = project.root.join(path);
+            transpile_file(&project.state, &path, &output, &mut stdout).unwrap();
+            String::from_utf8(stdout).unwrap()
+        };
+
+        let a = transpile(false, "a.js");
+        assert_eq!(transpile(true, "a.js"), format!("// a.js\n{a}\n"));
+        assert_eq!(transpile(true, "lib/b.js"), "// lib/b.js\nvar b;\n");
+    }
+
+    #[test]
+    fn bundle_map_has_on_disk_sources_with_contents() {
+        let default_doc = format!("{PROXY_WORKSPACE}/{DEFAULT_SCRIPT_FILENAME}");
+        let files = [
+            ("src/Main.js", "#include <Lib/Util.js>\nvar m = u;\n"),
+            ("Lib/Util.js", "var u = 1;\n"),
+            (default_doc.as_str(), "var d = 1;\n"),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        // lowercased copy of default document is not a source on case sensitive file system
+        let lowercased = default_doc.to_lowercase();
+        let _ = std::fs::remove_file(project.root.join(&lowercased));
+
+        let root = project.root.to_string_lossy().to_string();
+        let entry = project
+            .root
+            .join("src/Main.js")
+            .to_string_lossy()
+            .to_string();
+        let out = project
+            .root
+            .join("out/bundle.js")
+            .to_string_lossy()
+            .to_string();
+        bundle(&args(&[&entry, "-o", &out, "--project", &root])).unwrap();
+
+        let map = std::fs::read(project.root.join("out/bundle.js.map")).unwrap();
+        let map = serde_json::from_slice::<serde_json::Value>(&map).unwrap();
+        let sources = map["sources"].as_array().unwrap().iter();
+        let contents = map["sourcesContent"].as_array().unwrap().iter();
+        let mut sources = sources.zip(contents).collect::<Vec<_>>();
+        sources.sort_by_key(|(s, _)| s.as_str());
+
+        let expected = [
+            (
+                format!("../{}", default_doc.trim_start_matches("./")),
+                files[2].1,
+            ),
+            ("../Lib/Util.js".into(), files[1].1),
+            ("../src/Main.js".into(), files[0].1),
+        ];
+        let expected = expected.iter().map(|(s, c)| (json!(s), json!(c)));
+        let sources = sources.into_iter().map(|(s, c)| (s.clone(), c.clone()));
+        assert_eq!(sources.collect::<Vec<_>>(), expected.collect::<Vec<_>>());
+        assert!(map.get("sourceRoot").is_none_or(|r| r.is_null()));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod builder;
+pub mod cli;
+pub mod parser;
+pub mod proxy;
+pub mod state;
+pub mod types;
@@ -1,9 +1,3 @@
-mod builder;
-mod parser;
-mod proxy;
-mod state;
-mod types;
-
 use std::process::Stdio;
 use std::sync::{Arc, OnceLock};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
@@ -12,7 +6,8 @@ use tracing::level_filters::LevelFilter;
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
 
-use crate::proxy::Proxy;
+use glscript_language_server::cli;
+use glscript_language_server::proxy::{self, Proxy};
 
 static LOG_GUARD: std::sync::OnceLock<tracing_appender::non_blocking::WorkerGuard> =
     std::sync::OnceLock::new();
@@ -27,6 +22,15 @@ async fn main() {
         std::process::exit(1);
     }
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if let Some(res) = cli::run(&args) {
+        if let Err(err) = res {
+            eprintln!("error: {err:#}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let log_file = if std::env::args().any(|a| a.trim().eq("--log-file")) {
         let file_appender = tracing_appender::rolling::never(".", "gls-lsp.log");
         let (non_blocking, guard) = tracing_appender::non_blocking(file_appender);
@@ -1,30 +1,212 @@
 use derive_more::Constructor;
 use entry::{Rule, find_interpolations, get_pairs};
-use tokens::{Pending, RawToken, Span, StringLiteral};
+use errors::{directive_error, is_builtin_region_kind};
+use tokens::{Pending, RawToken, Span};
 
-pub use tokens::{LineCol, Token};
+pub use cache::ParseCache;
+pub use conditional::{Conditionals, conditional_blocks};
+pub use errors::{SyntaxError, SyntaxErrorKind, region_kind};
+pub use incremental::{Edit, reparse};
+pub use sql::{SqlHighlight, SqlRegion, SqlTokenKind};
+pub use tokens::{LineCol, StringLiteral, Token};
 
+mod cache;
+mod conditional;
 mod entry;
+mod errors;
+mod incremental;
+mod sql;
 mod tokens;
 
 #[derive(Constructor, Debug, Default)]
 pub struct Parse<'a> {
     pub compressed_tokens: Vec<Token<'a>>,
     pub str_interpolations: Vec<LineCol>,
+    /// sorted lines which start inside a multiline region, template string, comment
+    /// or include statement (unsafe restart points for [`reparse`])
+    pub nested_lines: Vec<u32>,
+    /// line where the fast grammar stopped if tokenized by the fallback grammar: some
+    /// construct which starts at the line is unterminated
+    pub recovered: Option<u32>,
+    pub errors: Vec<SyntaxError>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclarationKind {
+    Function,
+    Class,
+    Variable,
+}
+
+/// Top-level declaration of script
+#[derive(Constructor, Debug, Clone)]
+pub struct Declaration {
+    pub name: String,
+    pub kind: DeclarationKind,
+}
+
+/// parse `raw_text` where user-defined regions are only ones which kind is configured
+/// (`is_region_kind`), statements of others are common text
 #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
-pub fn parse<'a>(raw_text: &'a str) -> Parse<'a> {
+pub fn parse<'a>(raw_text: &'a str, is_region_kind: &dyn Fn(&str) -> bool) -> Parse<'a> {
+    tokenize(raw_text, 0, is_region_kind)
+}
+
+/// top-level `function`, `class` and `var`/`let`/`const` declarations (first name of
+/// declaration list) of script
+pub fn find_declarations(raw_text: &str) -> Vec<Declaration> {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let (pairs, _) = get_pairs(raw_text);
+    let mut depth = 0i32;
+    let mut keyword = None::<DeclarationKind>;
+    let mut declarations = vec![];
+
+    for ref pair in pairs {
+        if matches!(
+            pair.as_rule(),
+            Rule::Common | Rule::CommonFast | Rule::CommonWithLineEnding
+        ) {
+            let mut chars = pair.as_str().chars().peekable();
+            while let Some(c) = chars.next() {
+                match c {
+                    '{' | '(' | '[' => depth += 1,
+                    '}' | ')' | ']' => depth -= 1,
+                    '*' if keyword == Some(DeclarationKind::Function) => continue,
+                    c if c.is_whitespace() => continue,
+                    c if is_ident_char(c) => {
+                        let mut word = String::from(c);
+                        while let Some(c) = chars.next_if(|c| is_ident_char(*c)) {
+                            word.push(c);
+                        }
+
+                        if depth == 0
+                            && let Some(kind) = keyword.take()
+                        {
+                            declarations.push(Declaration::new(word, kind));
+                            continue;
+                        }
+
+                        keyword = match word.as_str() {
+                            "function" => Some(DeclarationKind::Function),
+                            "class" => Some(DeclarationKind::Class),
+                            "var" | "let" | "const" => Some(DeclarationKind::Variable),
+                            _ => None,
+                        };
+                        continue;
+                    }
+                    _ => {}
+                }
+                keyword = None;
+            }
+        }
+    }
+
+    declarations
+}
+
+/// start of string literal, template string or region (its open statement) which text
+/// contains `pos`
+pub fn find_literal(raw_text: &str, pos: &LineCol) -> Option<LineCol> {
+    let (pairs, _) = get_pairs(raw_text);
+    let (mut line, mut offset) = (0, 0);
+    let mut nesting = Vec::<(Rule, LineCol)>::new();
+
+    for ref pair in pairs {
+        let (rule, pair_str) = (pair.as_rule(), pair.as_str());
+        let (start, len) = (LineCol::new(line, offset), pair_str.chars().count() as u32);
+        let contains = |from: u32, to: u32| line == pos.line && (from..=to).contains(&pos.col);
+
+        match rule {
+            Rule::DoubleStringLiteral | Rule::SingleStringLiteral
+                if contains(offset + 1, offset + len - 1) =>
+            {
+                return Some(start);
+            }
+            Rule::TemplateStringChars | Rule::RegionChars if contains(offset, offset + len) => {
+                return nesting.last().map(|(_, start)| start.clone());
+            }
+            Rule::TemplateStringBracket
+                if matches!(nesting.last(), Some((Rule::TemplateStringBracket, _))) =>
+            {
+                nesting.pop();
+            }
+            Rule::TemplateStringBracket | Rule::PlaceholderOpenBracket | Rule::RegionOpen => {
+                nesting.push((rule, start))
+            }
+            Rule::PlaceholderCloseBracket
+                if matches!(nesting.last(), Some((Rule::PlaceholderOpenBracket, _))) =>
+            {
+                nesting.pop();
+            }
+            Rule::RegionClose => {
+                nesting.pop();
+            }
+            _ => {}
+        }
+
+        match matches!(rule, Rule::LineTerminator | Rule::CommonWithLineEnding) {
+            true => (line, offset) = (line + 1, 0),
+            false => offset += len,
+        };
+    }
+
+    None
+}
+
+/// tokenize `raw_text` which starts at the beginning of `first_line`
+fn tokenize<'a>(
+    raw_text: &'a str,
+    first_line: u32,
+    is_region_kind: &dyn Fn(&str) -> bool,
+) -> Parse<'a> {
     let raw_text_ptr = raw_text.as_ptr() as usize;
-    let pairs = get_pairs(raw_text);
-    let (mut line, mut offset, mut pending) = (0, 0, None::<Pending>);
+    let (pairs, recovered_pos) = get_pairs(raw_text);
+    let mut recovered = recovered_pos.map(|pos| first_line + lines_count(&raw_text[..pos]));
+    let (mut line, mut offset, mut pending) = (first_line, 0, None::<Pending>);
     let mut out = Vec::with_capacity(raw_text.lines().count());
     let mut str_i = vec![];
+    let mut nested = vec![];
+    let mut nesting = Vec::<Rule>::new();
+    let mut errors = vec![];
+    let mut region_open = None::<&str>;
+    // region of not configured kind: start of its lines once its open line is terminated
+    let mut unknown_region = None::<Option<usize>>;
+    let is_known_kind = |k: &str| is_builtin_region_kind(k) || is_region_kind(k);
 
     for ref pair in pairs {
-        let (rule, pair_str) = (pair.as_rule(), pair.as_str());
+        let (mut rule, pair_str) = (pair.as_rule(), pair.as_str());
         let pair_len = pair_str.len() as u32;
         let pos = unsafe { (pair_str.as_ptr() as usize).unchecked_sub(raw_text_ptr) };
+
+        match (rule, unknown_region) {
+            (Rule::RegionOpen, None) if !region_kind(pair_str).is_some_and(is_known_kind) => {
+                (rule, unknown_region) = (Rule::Common, Some(None));
+            }
+            (Rule::LineTerminator, Some(None)) => {
+                unknown_region = Some(Some(pos + pair_len as usize));
+            }
+            // lines of region are tokenized as code
+            (Rule::RegionClose, Some(Some(lines_start))) => {
+                if let Some(p) = pending.take() {
+                    out.push(p.flush(raw_text));
+                }
+
+                let lines = tokenize(&raw_text[lines_start..pos], line, is_region_kind);
+                let mut tokens = lines.compressed_tokens;
+                if let Some(Token::Eoi(end)) = tokens.pop() {
+                    (line, offset) = (end.line, 0);
+                }
+
+                out.extend(tokens);
+                str_i.extend(lines.str_interpolations);
+                nested.extend(lines.nested_lines);
+                errors.extend(lines.errors);
+                recovered = recovered.into_iter().chain(lines.recovered).min();
+                (rule, unknown_region) = (Rule::Common, None);
+            }
+            (_, Some(Some(_))) => continue,
+            _ => {}
+        }
         let lc = || LineCol { line, col: offset };
 
         let emit_span = || Span::new(lc(), pair_len);
@@ -47,9 +229,25 @@ pub fn parse<'a>(raw_text: &'a str) -> Parse<'a> {
 
         let uncommon_stmt = matches!(
             rule,
-            Rule::IncludeToken | Rule::IncludePath | Rule::RegionOpen | Rule::RegionClose
+            Rule::IncludeToken
+                | Rule::IncludePath
+                | Rule::RegionOpen
+                | Rule::RegionClose
+                | Rule::Directive
         );
 
+        let common_stmt = matches!(
+            rule,
+            Rule::Common | Rule::CommonFast | Rule::CommonWithLineEnding
+        );
+
+        // directive is a whole line which starts with common text
+        if common_stmt && offset == 0 {
+            let line_text = &raw_text[pos..];
+            let line_text = &line_text[..line_text.find(['\r', '\n']).unwrap_or(line_text.len())];
+            errors.extend(directive_error(line_text, &lc(), is_region_kind));
+        }
+
         if uncommon_stmt && let Some(p) = pending.take() {
             out.push(p.flush(raw_text));
         }
@@ -66,8 +264,22 @@ pub fn parse<'a>(raw_text: &'a str) -> Parse<'a> {
             Rule::CommonWithLineEnding => out.push(Token::CommonWithLineEnding(emit_token())),
             Rule::IncludeToken => out.push(Token::Include(emit_span())),
             Rule::IncludePath => out.push(Token::IncludePath(emit_sl())),
-            Rule::RegionOpen => out.push(Token::RegionOpen(emit_span())),
-            Rule::RegionClose => out.push(Token::RegionClose(emit_span())),
+            Rule::RegionOpen => {
+                region_open = region_kind(pair_str);
+                out.push(Token::RegionOpen(emit_span()))
+            }
+            Rule::RegionClose => {
+                if let Some(open) = region_open.take()
+                    && let Some(close) = region_kind(pair_str).filter(|k| *k != open)
+                {
+                    let message =
+                        format!("`#end{close}` closes `#{open}` region: expected `#end{open}`");
+                    let kind = SyntaxErrorKind::MismatchedRegionClose;
+                    errors.push(SyntaxError::new(kind, emit_span(), message));
+                }
+                out.push(Token::RegionClose(emit_span()))
+            }
+            Rule::Directive => out.push(Token::Directive(emit_token())),
             // common arms:
             Rule::RegionChars | Rule::TemplateStringChars => {
                 let RawToken { line_col, text } = emit_token();
@@ -81,9 +293,35 @@ pub fn parse<'a>(raw_text: &'a str) -> Parse<'a> {
             _ => pend_common(&mut pending, &mut str_i, None),
         };
 
+        match rule {
+            Rule::TemplateStringBracket
+                if matches!(nesting.last(), Some(Rule::TemplateStringBracket)) =>
+            {
+                nesting.pop();
+            }
+            Rule::TemplateStringBracket
+            | Rule::PlaceholderOpenBracket
+            | Rule::MultiLineCommentOpenBracket
+            | Rule::RegionOpen
+            | Rule::IncludeToken => nesting.push(rule),
+            Rule::PlaceholderCloseBracket
+            | Rule::MultiLineCommentCloseBracket
+            | Rule::RegionClose
+            | Rule::IncludePath => {
+                nesting.pop();
+            }
+            _ => {}
+        };
+
         match matches!(rule, Rule::LineTerminator | Rule::CommonWithLineEnding) {
-            true => (offset = 0, line += 1),
-            false => (offset += pair_str.chars().count() as u32, ()),
+            true => {
+                offset = 0;
+                line += 1;
+                if !nesting.is_empty() {
+                    nested.push(line);
+                }
+            }
+            false => offset += pair_str.chars().count() as u32,
         };
     }
 
@@ -95,14 +333,149 @@ pub fn parse<'a>(raw_text: &'a str) -> Parse<'a> {
         Some(Token::LineTerminator(r)) => (r.line + 1, 0).into(),
         Some(Token::CommonWithLineEnding(r)) => (r.line_col.line + 1, 0).into(),
         Some(Token::RegionClose(r)) => (r.line_col.line, r.line_col.col + r.len).into(),
-        Some(Token::Common(r)) => (r.line_col.line, r.line_col.col + r.text.len() as u32).into(),
+        Some(Token::Common(r) | Token::Directive(r)) => {
+            (r.line_col.line, r.line_col.col + r.text.len() as u32).into()
+        }
         Some(Token::IncludePath(r)) => {
             (r.line_col.line, r.line_col.col + r.lit.len() as u32 + 2).into()
         }
-        None => (0, 0).into(),
+        None => (first_line, 0).into(),
         _ => unreachable!(),
     };
 
     out.push(Token::Eoi(end_of_input));
-    Parse::new(out, str_i)
+    Parse::new(out, str_i, nested, recovered, errors)
+}
+
+/// count of line breaks (`\n`, `\r\n` or `\r`) of `text`
+pub fn lines_count(text: &str) -> u32 {
+    let b = text.as_bytes();
+    let breaks = b.iter().enumerate().filter(|(i, c)| match c {
+        b'\n' => true,
+        b'\r' => b.get(i + 1) != Some(&b'\n'),
+        _ => false,
+    });
+    breaks.count() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn include_paths<'a>(parse: &Parse<'a>) -> Vec<(u32, &'a str)> {
+        let tokens = parse.compressed_tokens.iter();
+        let paths = tokens.filter_map(|t| match t {
+            Token::IncludePath(sl) => Some((sl.line_col.line, sl.lit)),
+            _ => None,
+        });
+        paths.collect()
+    }
+
+    fn region_lines(parse: &Parse<'_>) -> Vec<u32> {
+        let tokens = parse.compressed_tokens.iter();
+        let regions = tokens.filter(|t| matches!(t, Token::RegionOpen(_) | Token::RegionClose(_)));
+        regions.map(|t| t.line_col().line).collect()
+    }
+
+    #[test]
+    fn lines_of_not_configured_region_are_code() {
+        let text = "#region\n#include <a.js>\nvar s = '%s';\n#endregion\n#include <b.js>\n";
+        let parse = super::parse(text, &|_| false);
+        assert!(region_lines(&parse).is_empty());
+        assert_eq!(include_paths(&parse), [(1, "a.js"), (4, "b.js")]);
+        assert_eq!(parse.str_interpolations, [LineCol::new(2, 9)]);
+        assert!(parse.nested_lines.is_empty() && parse.errors.is_empty());
+        assert!(matches!(parse.compressed_tokens.last(), Some(Token::Eoi(lc)) if lc.line == 5));
+
+        let parse = super::parse(text, &|k| k == "region");
+        assert_eq!(region_lines(&parse), [0, 3]);
+        assert_eq!(include_paths(&parse), [(4, "b.js")]);
+        assert_eq!(parse.nested_lines, [1, 2, 3]);
+    }
+
+    #[test]
+    fn template_placeholder_is_tokenized_once() {
+        let text = "var n = `x\n ${m}`;\nvar z = `${n}`;\nfunction f() {}\n";
+        let parse = super::parse(text, &|_| false);
+        let tokens = parse.compressed_tokens.iter();
+        let texts = tokens.filter_map(|t| match t {
+            Token::Common(r) | Token::CommonWithLineEnding(r) => Some(r.text),
+            _ => None,
+        });
+        assert_eq!(texts.collect::<String>(), text);
+        assert_eq!(parse.recovered, None);
+
+        let declarations = find_declarations(text).into_iter().map(|d| d.name);
+        assert_eq!(declarations.collect::<Vec<_>>(), ["n", "z", "f"]);
+    }
+
+    fn errors(text: &str) -> Vec<(SyntaxErrorKind, u32, u32, u32)> {
+        let errors = super::parse(text, &|k| k == "html").errors.into_iter();
+        let errors = errors.map(|e| {
+            (
+                e.kind,
+                e.span.line_col.line,
+                e.span.line_col.col,
+                e.span.len,
+            )
+        });
+        errors.collect()
+    }
+
+    #[test]
+    fn directive_errors_are_reported_by_kind() {
+        use SyntaxErrorKind::*;
+
+        assert_eq!(
+            errors("var a = 1;\n  #text\nabc\n"),
+            [(UnterminatedRegion, 1, 2, 5)]
+        );
+        assert_eq!(errors("abc\n#endsql\n"), [(UnmatchedRegionClose, 1, 0, 7)]);
+        assert_eq!(
+            errors("#html\nabc\n#endsql\n"),
+            [(MismatchedRegionClose, 2, 0, 7)]
+        );
+        assert_eq!(errors("#include <a.js\n"), [(UnclosedIncludePath, 0, 9, 5)]);
+        assert_eq!(
+            errors("#include \"a.js\n"),
+            [(UnclosedIncludePath, 0, 9, 5)]
+        );
+        assert_eq!(
+            errors("  #include\nvar a;\n"),
+            [(MissingIncludePath, 0, 2, 8)]
+        );
+        assert_eq!(errors("#html\n"), [(UnterminatedRegion, 0, 0, 5)]);
+    }
+
+    #[test]
+    fn directive_error_is_reported_only_if_alone_on_line() {
+        let lines = [
+            "var c = #textColor;",
+            "#textColor",
+            "#sqlite",
+            "a#include <a.js",
+            "#includes <a.js",
+            "var s = '#include <a.js';",
+            "// #endtext",
+            "#region",
+        ];
+
+        for line in lines {
+            assert!(errors(&format!("{line}\nabc\n")).is_empty(), "{line}");
+        }
+
+        // and is not a region statement
+        let parse = super::parse("var c = #textColor;\nabc\n#endtext\n", &|_| false);
+        assert_eq!(region_lines(&parse), [] as [u32; 0]);
+    }
+
+    #[test]
+    fn only_configured_region_is_unterminated() {
+        let text = "#region\nvar a = 1;\n";
+        assert!(super::parse(text, &|_| false).errors.is_empty());
+
+        let errors = super::parse(text, &|k| k == "region").errors;
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, SyntaxErrorKind::UnterminatedRegion);
+    }
 }
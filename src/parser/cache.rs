@@ -0,0 +1,104 @@
+use serde::{Deserialize, Serialize};
+
+use super::tokens::{LineCol, RawToken, Span, StringLiteral, Token};
+use super::{Parse, SyntaxError};
+
+/// [`Parse`] detached from parsed text: token texts are stored as byte ranges of text
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParseCache {
+    tokens: Vec<CachedToken>,
+    str_interpolations: Vec<LineCol>,
+    nested_lines: Vec<u32>,
+    recovered: Option<u32>,
+    errors: Vec<SyntaxError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum CachedToken {
+    Include(Span),
+    IncludePath(LineCol, usize, usize),
+    RegionOpen(Span),
+    RegionClose(Span),
+    Directive(LineCol, usize, usize),
+    LineTerminator(LineCol),
+    Common(LineCol, usize, usize),
+    CommonWithLineEnding(LineCol, usize, usize),
+    Eoi(LineCol),
+}
+
+impl ParseCache {
+    /// `text` should be the text parsed into `parse`
+    pub fn new(parse: &Parse<'_>, text: &str) -> Self {
+        let range = |t: &str| {
+            let pos = t.as_ptr() as usize - text.as_ptr() as usize;
+            (pos, pos + t.len())
+        };
+
+        let tokens = parse.compressed_tokens.iter().map(|t| match t {
+            Token::Include(s) => CachedToken::Include(s.clone()),
+            Token::IncludePath(sl) => {
+                let (start, end) = range(sl.lit);
+                CachedToken::IncludePath(sl.line_col.clone(), start, end)
+            }
+            Token::RegionOpen(s) => CachedToken::RegionOpen(s.clone()),
+            Token::RegionClose(s) => CachedToken::RegionClose(s.clone()),
+            Token::Directive(r) => {
+                let (start, end) = range(r.text);
+                CachedToken::Directive(r.line_col.clone(), start, end)
+            }
+            Token::LineTerminator(lc) => CachedToken::LineTerminator(lc.clone()),
+            Token::Common(r) => {
+                let (start, end) = range(r.text);
+                CachedToken::Common(r.line_col.clone(), start, end)
+            }
+            Token::CommonWithLineEnding(r) => {
+                let (start, end) = range(r.text);
+                CachedToken::CommonWithLineEnding(r.line_col.clone(), start, end)
+            }
+            Token::Eoi(lc) => CachedToken::Eoi(lc.clone()),
+        });
+
+        Self {
+            tokens: tokens.collect(),
+            str_interpolations: parse.str_interpolations.clone(),
+            nested_lines: parse.nested_lines.clone(),
+            recovered: parse.recovered,
+            errors: parse.errors.clone(),
+        }
+    }
+
+    /// returns none if token ranges don't fit `text`
+    pub fn restore(self, text: &str) -> Option<Parse<'_>> {
+        let mut tokens = Vec::with_capacity(self.tokens.len());
+
+        for t in self.tokens {
+            tokens.push(match t {
+                CachedToken::Include(s) => Token::Include(s),
+                CachedToken::IncludePath(lc, start, end) => {
+                    Token::IncludePath(StringLiteral::new(lc, text.get(start..end)?))
+                }
+                CachedToken::RegionOpen(s) => Token::RegionOpen(s),
+                CachedToken::RegionClose(s) => Token::RegionClose(s),
+                CachedToken::Directive(lc, start, end) => {
+                    Token::Directive(RawToken::new(lc, text.get(start..end)?))
+                }
+                CachedToken::LineTerminator(lc) => Token::LineTerminator(lc),
+                CachedToken::Common(lc, start, end) => {
+                    Token::Common(RawToken::new(lc, text.get(start..end)?))
+                }
+                CachedToken::CommonWithLineEnding(lc, start, end) => {
+                    Token::CommonWithLineEnding(RawToken::new(lc, text.get(start..end)?))
+                }
+                CachedToken::Eoi(lc) => Token::Eoi(lc),
+            });
+        }
+
+        Some(Parse::new(
+            tokens,
+            self.str_interpolations,
+            self.nested_lines,
+            self.recovered,
+            self.errors,
+        ))
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use super::errors::{SyntaxError, SyntaxErrorKind};
+use super::tokens::{Span, Token};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirectiveKind {
+    Define,
+    Ifdef,
+    Ifndef,
+    Else,
+    Endif,
+}
+
+impl DirectiveKind {
+    /// kind and name argument (empty if missing) of [`Token::Directive`] text
+    pub fn parse(text: &str) -> Option<(Self, &str)> {
+        let text = text.strip_prefix('#')?;
+        let name_len = text.find(|c: char| !c.is_ascii_alphabetic());
+        let (name, arg) = text.split_at(name_len.unwrap_or(text.len()));
+        let kind = match name {
+            "define" => DirectiveKind::Define,
+            "ifdef" => DirectiveKind::Ifdef,
+            "ifndef" => DirectiveKind::Ifndef,
+            "else" => DirectiveKind::Else,
+            "endif" => DirectiveKind::Endif,
+            _ => return None,
+        };
+        Some((kind, arg.split_whitespace().next().unwrap_or("")))
+    }
+}
+
+/// Branches of conditional directives evaluated by defined names
+#[derive(Debug, Default)]
+pub struct Conditionals {
+    /// per token: directive or token of inactive branch, which are not emitted
+    inactive: Vec<bool>,
+    /// lines (end exclusive) of inactive branches between directive lines
+    pub inactive_lines: Vec<Range<u32>>,
+    /// names defined before evaluation and by `#define` of active branches
+    pub defined: HashSet<String>,
+    pub errors: Vec<SyntaxError>,
+}
+
+struct Branch<'a> {
+    directive: &'a str,
+    span: Span,
+    active: bool,
+    parent_active: bool,
+    has_else: bool,
+}
+
+impl Conditionals {
+    /// `#define` applies to the rest of tokens
+    pub fn evaluate(tokens: &[Token<'_>], defined: &HashSet<String>) -> Self {
+        let mut this = Self {
+            inactive: Vec::with_capacity(tokens.len()),
+            defined: defined.clone(),
+            ..Default::default()
+        };
+        let mut stack = Vec::<Branch>::new();
+        let is_active = |stack: &[Branch]| stack.last().is_none_or(|b| b.active);
+        let mut inactive_since = |b: &Branch, line: u32| {
+            if b.parent_active && !b.active {
+                this.inactive_lines.push(b.span.line_col.line + 1..line);
+            }
+        };
+
+        for t in tokens {
+            let Token::Directive(rt) = t else {
+                this.inactive.push(!is_active(&stack));
+                continue;
+            };
+
+            this.inactive.push(true);
+            let Some((kind, name)) = DirectiveKind::parse(rt.text) else {
+                continue;
+            };
+
+            let directive = rt.text.split_whitespace().next().unwrap_or(rt.text);
+            let span = Span::new(rt.line_col.clone(), directive.chars().count() as u32);
+            let line = rt.line_col.line;
+            let parent_active = is_active(&stack);
+
+            let unmatched = match kind {
+                DirectiveKind::Define if parent_active && !name.is_empty() => {
+                    this.defined.insert(name.to_string());
+                    None
+                }
+                DirectiveKind::Define => None,
+                DirectiveKind::Ifdef | DirectiveKind::Ifndef => {
+                    let is_defined = this.defined.contains(name);
+                    let active = parent_active && is_defined == (kind == DirectiveKind::Ifdef);
+                    let has_else = false;
+                    stack.push(Branch {
+                        directive,
+                        span,
+                        active,
+                        parent_active,
+                        has_else,
+                    });
+                    continue;
+                }
+                DirectiveKind::Else => match stack.last_mut() {
+                    Some(b) if !b.has_else => {
+                        inactive_since(b, line);
+                        (b.directive, b.span, b.has_else) = (directive, span, true);
+                        b.active = b.parent_active && !b.active;
+                        continue;
+                    }
+                    Some(_) => Some("`#else` after `#else` of the same branch"),
+                    None => Some("`#else` without opening `#ifdef`"),
+                },
+                DirectiveKind::Endif => match stack.pop() {
+                    Some(b) => {
+                        inactive_since(&b, line);
+                        None
+                    }
+                    None => Some("`#endif` without opening `#ifdef`"),
+                },
+            };
+
+            if let Some(message) = unmatched {
+                let kind = SyntaxErrorKind::UnmatchedConditional;
+                this.errors
+                    .push(SyntaxError::new(kind, span, message.to_string()));
+            }
+        }
+
+        let eoi_line = tokens.last().map(|t| t.line_col().line).unwrap_or(0);
+        for b in stack {
+            inactive_since(&b, eoi_line + 1);
+            let message = format!("unterminated `{}` branch: expected `#endif`", b.directive);
+            let kind = SyntaxErrorKind::UnterminatedConditional;
+            this.errors.push(SyntaxError::new(kind, b.span, message));
+        }
+
+        this
+    }
+
+    /// whether token at `idx` is not emitted
+    pub fn is_inactive(&self, idx: usize) -> bool {
+        self.inactive.get(idx).copied().unwrap_or(false)
+    }
+}
+
+/// lines (end exclusive) of conditional blocks from opening directive to `#endif`
+pub fn conditional_blocks(tokens: &[Token<'_>]) -> Vec<Range<u32>> {
+    let mut blocks = vec![];
+    let mut opened = vec![];
+
+    for t in tokens {
+        let Token::Directive(rt) = t else {
+            continue;
+        };
+
+        match DirectiveKind::parse(rt.text) {
+            Some((DirectiveKind::Ifdef | DirectiveKind::Ifndef, _)) => {
+                opened.push(rt.line_col.line)
+            }
+            Some((DirectiveKind::Endif, _)) => {
+                if let Some(start) = opened.pop() {
+                    blocks.push(start..rt.line_col.line + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let eoi_line = tokens.last().map(|t| t.line_col().line).unwrap_or(0);
+    blocks.extend(opened.into_iter().map(|start| start..eoi_line + 1));
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    fn errors(text: &str) -> Vec<(SyntaxErrorKind, u32, u32)> {
+        let parse = parse(text, &|_| false);
+        let conditionals = Conditionals::evaluate(&parse.compressed_tokens, &HashSet::new());
+        let errors = conditionals.errors.into_iter();
+        errors
+            .map(|e| (e.kind, e.span.line_col.line, e.span.len))
+            .collect()
+    }
+
+    #[test]
+    fn conditional_errors_are_reported_by_kind() {
+        use SyntaxErrorKind::*;
+
+        assert_eq!(
+            errors("#ifdef A\nvar a;\n"),
+            [(UnterminatedConditional, 0, 6)]
+        );
+        assert_eq!(errors("var a;\n#endif\n"), [(UnmatchedConditional, 1, 6)]);
+        assert_eq!(errors("#else\n"), [(UnmatchedConditional, 0, 5)]);
+
+        let text = "#ifndef A\n#else\n#else\n#endif\n";
+        assert_eq!(errors(text), [(UnmatchedConditional, 2, 5)]);
+        assert!(errors("#ifdef A\n#define B\n#else\n#endif\n").is_empty());
+    }
+}
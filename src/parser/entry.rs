@@ -8,12 +8,13 @@ mod grammar {
 
 pub use grammar::{GlScriptSubsetGrammar, Ident, Rule};
 
-#[allow(unused)]
 pub type Pair<'a> = faster_pest::Pair2<'a, Ident<'a>>;
 pub type Pairs<'a> = faster_pest::Pairs2<'a, Ident<'a>>;
 
-pub fn get_pairs<'a>(raw_text: &'a str) -> Pairs<'a> {
-    let pairs = parse_raw_text(Rule::SourceFileFast, raw_text);
+/// returns pairs and position where the fast grammar stopped if the fallback grammar was
+/// used (construct which starts there is unterminated)
+pub fn get_pairs<'a>(raw_text: &'a str) -> (impl Iterator<Item = Pair<'a>> + Clone, Option<usize>) {
+    let pairs = dedup(parse_raw_text(Rule::SourceFileFast, raw_text));
     let (mut pos, mut ok) = (0, true);
 
     for p in pairs.clone() {
@@ -29,11 +30,23 @@ pub fn get_pairs<'a>(raw_text: &'a str) -> Pairs<'a> {
     }
 
     match ok && pos == raw_text.len() {
-        true => pairs,
-        false => parse_raw_text(Rule::SourceFile, raw_text), // fallback
+        true => (pairs, None),
+        false => (dedup(parse_raw_text(Rule::SourceFile, raw_text)), Some(pos)), // fallback
     }
 }
 
+/// pairs without repeated ones: generated parser repeats `PlaceholderCloseBracket` pair
+/// which is checked by lookahead of template expression
+fn dedup(pairs: Pairs<'_>) -> impl Iterator<Item = Pair<'_>> + Clone {
+    let mut end = 0;
+    pairs.filter(move |p| {
+        let span = p.as_span();
+        let is_repeated = span.start() < end;
+        end = end.max(span.end());
+        !is_repeated
+    })
+}
+
 fn parse_raw_text(entry_rule: Rule, raw_text: &str) -> Pairs<'_> {
     GlScriptSubsetGrammar::parse(entry_rule, raw_text)
         .unwrap()
@@ -0,0 +1,127 @@
+use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
+
+use super::tokens::{LineCol, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SyntaxErrorKind {
+    UnterminatedRegion,
+    UnmatchedRegionClose,
+    MismatchedRegionClose,
+    UnclosedIncludePath,
+    MissingIncludePath,
+    UnterminatedConditional,
+    UnmatchedConditional,
+    InvalidSql,
+}
+
+impl SyntaxErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SyntaxErrorKind::UnterminatedRegion => "unterminated-region",
+            SyntaxErrorKind::UnmatchedRegionClose => "unmatched-region-close",
+            SyntaxErrorKind::MismatchedRegionClose => "mismatched-region-close",
+            SyntaxErrorKind::UnclosedIncludePath => "unclosed-include-path",
+            SyntaxErrorKind::MissingIncludePath => "missing-include-path",
+            SyntaxErrorKind::UnterminatedConditional => "unterminated-conditional",
+            SyntaxErrorKind::UnmatchedConditional => "unmatched-conditional",
+            SyntaxErrorKind::InvalidSql => "invalid-sql",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Constructor, Serialize, Deserialize)]
+pub struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    pub span: Span,
+    pub message: String,
+}
+
+const REGION_KINDS: [&str; 2] = ["text", "sql"];
+const RESERVED_WORDS: [&str; 6] = ["include", "define", "ifdef", "ifndef", "else", "endif"];
+
+/// region kind of `RegionOpen` or `RegionClose` statement text
+pub fn region_kind(stmt: &str) -> Option<&str> {
+    let builtin = stmt.match_indices('#').find_map(|(pos, _)| {
+        let tail = &stmt[pos + 1..];
+        region_kind_prefix(tail.strip_prefix("end").unwrap_or(tail))
+    });
+    builtin.or_else(|| custom_region_kind(stmt))
+}
+
+/// kind of user-defined region of standalone `#kind` or `#endkind` line
+pub fn custom_region_kind(line: &str) -> Option<&str> {
+    let word = line.trim().strip_prefix('#')?;
+    let kind = word.strip_prefix("end").unwrap_or(word);
+    let is_kind_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_';
+    let is_kind =
+        kind.starts_with(|c: char| c.is_ascii_lowercase()) && kind.chars().all(is_kind_char);
+    (is_kind && !RESERVED_WORDS.contains(&word)).then_some(kind)
+}
+
+/// built-in `text` or `sql` region kind (other kinds are regions only if configured)
+pub fn is_builtin_region_kind(kind: &str) -> bool {
+    REGION_KINDS.contains(&kind)
+}
+
+/// error of directive statement which was not matched by grammar and left as common text:
+/// directive is reported only if it is alone on its `line` (like `#sql` or `#include <a.js`,
+/// but not `#sqlite` or `a#include`) and region directives only of configured kinds
+pub fn directive_error(
+    line: &str,
+    line_col: &LineCol,
+    is_region_kind: &dyn Fn(&str) -> bool,
+) -> Option<SyntaxError> {
+    let directive = line.trim();
+    let pos = line.len() - line.trim_start().len();
+    let span = |pos: usize, len: usize| {
+        let col = line_col.col + line[..pos].chars().count() as u32;
+        Span::new((line_col.line, col).into(), len as u32)
+    };
+
+    let is_kind = |k: &str| is_builtin_region_kind(k) || is_region_kind(k);
+    if let Some(kind) = custom_region_kind(line).filter(|k| is_kind(k)) {
+        let (kind_, message) = match directive.starts_with("#end") {
+            true => (
+                SyntaxErrorKind::UnmatchedRegionClose,
+                format!("`#end{kind}` without opening `#{kind}`"),
+            ),
+            false => (
+                SyntaxErrorKind::UnterminatedRegion,
+                format!("unterminated `#{kind}` region: expected `#end{kind}`"),
+            ),
+        };
+        return Some(SyntaxError::new(kind_, span(pos, directive.len()), message));
+    }
+
+    let path = directive.strip_prefix("#include")?;
+    if path.starts_with(|c: char| !c.is_whitespace()) {
+        return None;
+    }
+
+    let path_trimmed = path.trim_start();
+    let path_pos = pos + 8 + path.len() - path_trimmed.len();
+    let closing = match path_trimmed.chars().next() {
+        Some('<') => '>',
+        Some(q @ ('"' | '\'')) => q,
+        Some(_) => return None,
+        None => {
+            let message = "expected include path after `#include`".to_string();
+            let kind_ = SyntaxErrorKind::MissingIncludePath;
+            return Some(SyntaxError::new(kind_, span(pos, 8), message));
+        }
+    };
+
+    if path_trimmed[1..].contains(closing) {
+        return None;
+    }
+
+    let message = format!("unclosed include path: expected `{closing}`");
+    let len = path_trimmed.chars().count();
+    let kind_ = SyntaxErrorKind::UnclosedIncludePath;
+    Some(SyntaxError::new(kind_, span(path_pos, len), message))
+}
+
+fn region_kind_prefix(text: &str) -> Option<&'static str> {
+    REGION_KINDS.into_iter().find(|k| text.starts_with(k))
+}
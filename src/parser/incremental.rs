@@ -0,0 +1,312 @@
+use derive_more::Constructor;
+
+use super::errors::custom_region_kind;
+use super::{Parse, Token, parse, tokenize};
+
+/// Change of previous text: bytes `start..end` on lines `start_line..=end_line` are
+/// replaced by `len` bytes which contain `lines` line breaks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Constructor)]
+pub struct Edit {
+    pub start: usize,
+    pub end: usize,
+    pub len: usize,
+    pub start_line: u32,
+    pub end_line: u32,
+    pub lines: u32,
+}
+
+impl Edit {
+    /// edit of previous text which covers this edit and `next` one (made after this edit)
+    pub fn then(self, next: Edit) -> Edit {
+        let (new_end, new_end_line) = (self.start + self.len, self.start_line + self.lines);
+
+        // end of `next` edit in previous text
+        let (next_end, next_end_line) = match next.end {
+            end if end <= self.start => (end, next.end_line),
+            end if end >= new_end => (
+                end - new_end + self.end,
+                next.end_line - new_end_line + self.end_line,
+            ),
+            _ => (self.end, self.end_line),
+        };
+
+        let (start, start_line) = match next.start < self.start {
+            true => (next.start, next.start_line),
+            false => (self.start, self.start_line),
+        };
+        let (end, end_line) = match next_end > self.end {
+            true => (next_end, next_end_line),
+            false => (self.end, self.end_line),
+        };
+
+        let byte_delta = |e: &Edit| e.len as isize - (e.end - e.start) as isize;
+        let line_delta = |e: &Edit| e.lines as i64 - (e.end_line - e.start_line) as i64;
+        let len = (end - start) as isize + byte_delta(&self) + byte_delta(&next);
+        let lines = (end_line - start_line) as i64 + line_delta(&self) + line_delta(&next);
+        Edit::new(start, end, len as usize, start_line, end_line, lines as u32)
+    }
+}
+
+/// re-tokenize only lines touched by `edit` of `prev_text` (`text` is edited text)
+///
+/// window of re-tokenized lines is extended to lines which start outside of any
+/// multiline construct, so tokens before and after the window are reused. If the
+/// window is unterminated (or was) it's extended to the end of text
+#[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
+pub fn reparse<'a>(
+    prev: &Parse<'_>,
+    prev_text: &str,
+    text: &'a str,
+    edit: Edit,
+    is_region_kind: &dyn Fn(&str) -> bool,
+) -> Parse<'a> {
+    if !matches!(prev.compressed_tokens.last(), Some(Token::Eoi(_))) {
+        return parse(text, is_region_kind);
+    }
+
+    let is_nested = |line: u32| prev.nested_lines.binary_search(&line).is_ok();
+    let prev_line = |start: usize| line_start(prev_text, line_end_before(prev_text, start));
+
+    // restart from line before the edit (or from unterminated construct)
+    let (mut start_line, mut start) = (edit.start_line, line_start(prev_text, edit.start));
+    let min_line = start_line.saturating_sub(1);
+    let min_line = prev.recovered.map_or(min_line, |line| line.min(min_line));
+    while start_line > 0
+        && (start_line > min_line
+            || is_nested(start_line)
+            || is_trivia(&prev_text[prev_line(start)..start]))
+    {
+        (start_line, start) = (start_line - 1, prev_line(start));
+    }
+
+    let (mut end_line, mut end) = (edit.end_line + 1, next_line_start(prev_text, edit.end));
+    while let Some(pos) = end.filter(|_| is_nested(end_line)) {
+        (end_line, end) = (end_line + 1, next_line_start(prev_text, pos));
+    }
+
+    // unterminated construct of previous text can be terminated by the change
+    let was_unterminated = prev.recovered.is_some_and(|line| line < end_line);
+    let byte_delta = edit.len as isize - (edit.end - edit.start) as isize;
+    let line_delta = edit.lines as i64 - (edit.end_line - edit.start_line) as i64;
+
+    let mut window = None;
+    if let Some(end) = end.filter(|_| !was_unterminated) {
+        let end = (end as isize + byte_delta) as usize;
+        let parse = tokenize(&text[start..end], start_line, is_region_kind);
+        let is_dangling = parse.compressed_tokens.iter().any(has_dangling_directive);
+        window = (parse.recovered.is_none() && !is_dangling).then_some(parse);
+    }
+
+    let (window, end_line) = match window {
+        Some(window) => (window, Some(end_line)),
+        None => (tokenize(&text[start..], start_line, is_region_kind), None),
+    };
+
+    let before_window = |t: &Token<'_>| t.line_col().line < start_line;
+    let before_suffix = |t: &Token<'_>| end_line.is_none_or(|end| t.line_col().line < end);
+    let prev_tokens = &prev.compressed_tokens;
+    let (prefix_end, suffix_start) = (
+        prev_tokens.partition_point(before_window),
+        prev_tokens.partition_point(before_suffix),
+    );
+
+    let mut tokens = Vec::with_capacity(prev_tokens.len());
+    tokens.extend((prev_tokens[..prefix_end].iter()).map(|t| t.rebase(prev_text, text, 0, 0)));
+    tokens.extend(
+        (window.compressed_tokens.into_iter())
+            .filter(|t| end_line.is_none() || !matches!(t, Token::Eoi(_))),
+    );
+    tokens.extend(
+        (prev_tokens[suffix_start..].iter())
+            .map(|t| t.rebase(prev_text, text, byte_delta, line_delta)),
+    );
+
+    let lines = (start_line, end_line, line_delta);
+    let str_interpolations = splice(
+        &prev.str_interpolations,
+        window.str_interpolations,
+        lines,
+        |lc| &mut lc.line,
+    );
+    let nested_lines = splice(&prev.nested_lines, window.nested_lines, lines, |l| l);
+    let errors = splice(&prev.errors, window.errors, lines, |e| {
+        &mut e.span.line_col.line
+    });
+
+    let recovered = match end_line {
+        Some(_) => prev.recovered.map(|l| (l as i64 + line_delta) as u32),
+        None => window.recovered,
+    };
+
+    Parse::new(tokens, str_interpolations, nested_lines, recovered, errors)
+}
+
+/// items of `prev` before and after the window (shifted by line delta) around `window` items
+fn splice<T: Clone>(
+    prev: &[T],
+    window: Vec<T>,
+    (start_line, end_line, line_delta): (u32, Option<u32>, i64),
+    line: impl Fn(&mut T) -> &mut u32,
+) -> Vec<T> {
+    let mut out = Vec::with_capacity(prev.len() + window.len());
+    out.extend(
+        prev.iter()
+            .cloned()
+            .filter_map(|mut i| (*line(&mut i) < start_line).then_some(i)),
+    );
+    out.extend(window);
+
+    if let Some(end_line) = end_line {
+        out.extend(prev.iter().cloned().filter_map(|mut i| {
+            let l = line(&mut i);
+            (*l >= end_line).then(|| *l = (*l as i64 + line_delta) as u32)?;
+            Some(i)
+        }));
+    }
+
+    out
+}
+
+/// directive which is not a part of region or include statement yet, but could become
+/// a part of it depending on text outside of the window
+fn has_dangling_directive(token: &Token<'_>) -> bool {
+    let text = match token {
+        Token::Common(r) | Token::CommonWithLineEnding(r) => r.text,
+        _ => return false,
+    };
+
+    if ["#text", "#sql", "#end"].iter().any(|d| text.contains(d)) {
+        return true;
+    }
+
+    if text.lines().any(|l| custom_region_kind(l).is_some()) {
+        return true;
+    }
+
+    ["#include", "import"]
+        .iter()
+        .any(|d| (text.match_indices(d)).any(|(i, _)| is_trivia(&text[i + d.len()..])))
+}
+
+/// empty line or line which starts with comment
+fn is_trivia(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with("//") || line.starts_with("/*")
+}
+
+/// start of line which contains `pos`
+fn line_start(text: &str, pos: usize) -> usize {
+    text[..pos].rfind(['\n', '\r']).map_or(0, |i| i + 1)
+}
+
+/// end (without line ending) of line before line which starts at `line_start`
+fn line_end_before(text: &str, line_start: usize) -> usize {
+    match &text.as_bytes()[..line_start] {
+        [.., b'\r', b'\n'] => line_start - 2,
+        _ => line_start - 1,
+    }
+}
+
+/// start of line after line which contains `pos`
+fn next_line_start(text: &str, pos: usize) -> Option<usize> {
+    let i = pos + text[pos..].find(['\n', '\r'])?;
+    match text.as_bytes()[i..] {
+        [b'\r', b'\n', ..] => Some(i + 2),
+        _ => Some(i + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::lines_count;
+
+    const DOCUMENT: &str = "\
+#include <a.js>
+var a = 1;
+#text
+ select `a` %b
+#endtext
+var s = `x
+ ${a + `y${'%c'}`}
+ z`;
+/* block
+ comment */ var d = \"%d\";
+#html
+ <a>${d}</a>
+#endhtml
+function f() {}
+";
+
+    fn is_region_kind(kind: &str) -> bool {
+        kind == "html"
+    }
+
+    fn edit(text: &str, start: usize, end: usize, new_text: &str) -> Edit {
+        let line = |pos: usize| lines_count(&text[..pos]);
+        let (len, lines) = (new_text.len(), lines_count(new_text));
+        Edit::new(start, end, len, line(start), line(end), lines)
+    }
+
+    /// reparse of `prev_text` edited at `start..end` is equal to parse of edited text
+    fn assert_reparse_eq(prev_text: &str, start: usize, end: usize, new_text: &str) {
+        let text = [&prev_text[..start], new_text, &prev_text[end..]].concat();
+        let prev = parse(prev_text, &is_region_kind);
+        let edit = edit(prev_text, start, end, new_text);
+
+        let reparsed = reparse(&prev, prev_text, &text, edit, &is_region_kind);
+        let parsed = parse(&text, &is_region_kind);
+        assert_eq!(format!("{reparsed:?}"), format!("{parsed:?}"), "{text:?}");
+    }
+
+    fn assert_edits_eq(prev_text: &str, new_texts: &[&str]) {
+        let positions = (prev_text.char_indices().map(|(i, _)| i)).chain([prev_text.len()]);
+        let positions = positions.collect::<Vec<_>>();
+
+        for (i, &start) in positions.iter().enumerate() {
+            for new_text in new_texts {
+                assert_reparse_eq(prev_text, start, start, new_text);
+            }
+            if let Some(&end) = positions.get(i + 1) {
+                assert_reparse_eq(prev_text, start, end, "");
+            }
+        }
+    }
+
+    #[test]
+    fn reparse_of_region_edit_is_equal_to_parse() {
+        assert_edits_eq(DOCUMENT, &["x", "\n", "%e", "#endtext", "\n#endtext\n"]);
+        assert_edits_eq(DOCUMENT, &["#text\n", "\n#html\n", "\n#endhtml\n", "`"]);
+    }
+
+    #[test]
+    fn reparse_of_template_string_edit_is_equal_to_parse() {
+        assert_edits_eq(DOCUMENT, &["`", "${", "}", "'", "\"", "\\", "\r\n"]);
+    }
+
+    #[test]
+    fn reparse_of_block_comment_edit_is_equal_to_parse() {
+        assert_edits_eq(DOCUMENT, &["/*", "*/", "//", "/", "*"]);
+    }
+
+    #[test]
+    fn reparse_of_unterminated_text_is_equal_to_parse() {
+        let text = "var a = 1;\nvar s = `x\n${a}\nvar b = 2;\n/* c\nvar c = 3;\n";
+        assert!(parse(text, &is_region_kind).recovered.is_some());
+        assert_edits_eq(text, &["`", "*/", "x", "\n"]);
+    }
+
+    #[test]
+    fn merged_edits_are_equal_to_covering_edit() {
+        let text = "a\nbc\ndef\n";
+        // "a\nbc\ndef\n" -> "a\nb12\n3c\ndef\n" -> "a\nb12\n3c\nd\nf\n"
+        let first = edit(text, 3, 3, "12\n3");
+        let second = edit("a\nb12\n3c\ndef\n", 10, 11, "\n");
+        assert_eq!(first.then(second), edit(text, 3, 7, "12\n3c\nd\n"));
+
+        // second edit before first one: "a\nbc\ndef\n" -> "a\nbcef\n" -> "\nbcef\n"
+        let first = edit(text, 4, 6, "");
+        let second = edit("a\nbcef\n", 0, 1, "");
+        assert_eq!(first.then(second), edit(text, 0, 6, "\nbc"));
+    }
+}
@@ -0,0 +1,364 @@
+use std::ops::Range;
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use sqlparser::dialect::GenericDialect;
+use sqlparser::keywords::{self, Keyword};
+use sqlparser::parser::{Parser, ParserError};
+use sqlparser::tokenizer::{Location, Token as SqlToken, Tokenizer, Whitespace};
+
+use super::errors::{SyntaxError, SyntaxErrorKind};
+use super::tokens::{LineCol, Span};
+
+/// keywords highlighted besides reserved ones (other keywords like `name` or `id` are
+/// mostly used as identifiers)
+const KEYWORDS: [Keyword; 30] = [
+    Keyword::ALL,
+    Keyword::ALTER,
+    Keyword::AND,
+    Keyword::ANY,
+    Keyword::AS,
+    Keyword::ASC,
+    Keyword::BETWEEN,
+    Keyword::BY,
+    Keyword::CASE,
+    Keyword::CAST,
+    Keyword::CREATE,
+    Keyword::DELETE,
+    Keyword::DESC,
+    Keyword::DISTINCT,
+    Keyword::DROP,
+    Keyword::ELSE,
+    Keyword::EXISTS,
+    Keyword::FALSE,
+    Keyword::IN,
+    Keyword::INSERT,
+    Keyword::IS,
+    Keyword::LIKE,
+    Keyword::NOT,
+    Keyword::NULL,
+    Keyword::OR,
+    Keyword::TABLE,
+    Keyword::THEN,
+    Keyword::TRUE,
+    Keyword::UPDATE,
+    Keyword::WHEN,
+];
+
+/// location which sqlparser appends to error message
+static ERROR_LOCATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)^(.*) at Line: (\d+), Column: (\d+)$").unwrap());
+
+/// Content of `#sql` region between open and close statement lines
+#[derive(Debug)]
+pub struct SqlRegion {
+    /// index of `RegionOpen` token
+    pub open_idx: usize,
+    /// content lines (end exclusive)
+    pub lines: Range<u32>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlTokenKind {
+    Keyword,
+    String,
+    Number,
+    Comment,
+    /// `%ident` string interpolation
+    Parameter,
+}
+
+/// Highlighted SQL token of single line (utf16 columns)
+#[derive(Debug)]
+pub struct SqlHighlight {
+    pub line_col: LineCol,
+    pub len: u32,
+    pub kind: SqlTokenKind,
+}
+
+impl SqlRegion {
+    pub fn new(open_idx: usize, lines: Range<u32>, text: String) -> Self {
+        Self {
+            open_idx,
+            lines,
+            text,
+        }
+    }
+
+    /// syntax errors of statements (reported one per region as parser stops on the first)
+    pub fn errors(&self) -> Vec<SyntaxError> {
+        let sql = self.sql();
+        let err = match Parser::parse_sql(&GenericDialect, &sql) {
+            Ok(_) | Err(ParserError::RecursionLimitExceeded) => return vec![],
+            Err(ParserError::TokenizerError(e) | ParserError::ParserError(e)) => e,
+        };
+
+        let (message, location) = match ERROR_LOCATION.captures(&err) {
+            Some(caps) => {
+                let num = |i: usize| caps[i].parse::<u64>().unwrap_or(0);
+                (caps[1].to_string(), Location::new(num(2), num(3)))
+            }
+            None => (err.clone(), self.end_location()),
+        };
+
+        let Some(line) = self.line(location.line) else {
+            return vec![];
+        };
+
+        let start = location.column.saturating_sub(1) as usize;
+        let word = line.chars().skip(start);
+        let word = word.take_while(|c| !c.is_whitespace()).collect::<String>();
+        let len = word.encode_utf16().count().max(1) as u32;
+        let line_col = self.line_col(location);
+        let span = Span::new(line_col, len);
+
+        vec![SyntaxError::new(SyntaxErrorKind::InvalidSql, span, message)]
+    }
+
+    /// keywords, literals, comments and parameters split by lines
+    pub fn highlights(&self) -> Vec<SqlHighlight> {
+        let sql = self.sql();
+        let params = self.params();
+        let mut highlights = params
+            .iter()
+            .map(|(line_col, len)| {
+                let (line_col, len) = (line_col.clone(), *len);
+                let kind = SqlTokenKind::Parameter;
+                SqlHighlight {
+                    line_col,
+                    len,
+                    kind,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let Ok(tokens) = Tokenizer::new(&GenericDialect, &sql).tokenize_with_location() else {
+            return highlights;
+        };
+
+        for t in tokens {
+            let kind = match &t.token {
+                SqlToken::Word(w) if w.quote_style.is_none() && is_keyword(w.keyword) => {
+                    SqlTokenKind::Keyword
+                }
+                SqlToken::Number(..) => SqlTokenKind::Number,
+                SqlToken::Whitespace(
+                    Whitespace::SingleLineComment { .. } | Whitespace::MultiLineComment(_),
+                ) => SqlTokenKind::Comment,
+                SqlToken::SingleQuotedString(_)
+                | SqlToken::DoubleQuotedString(_)
+                | SqlToken::NationalStringLiteral(_)
+                | SqlToken::EscapedStringLiteral(_)
+                | SqlToken::DollarQuotedString(_) => SqlTokenKind::String,
+                _ => continue,
+            };
+
+            let (start, end) = (t.span.start, t.span.end);
+            for line in start.line..=end.line {
+                let Some(text) = self.line(line) else {
+                    continue;
+                };
+
+                let from = if line == start.line { start.column } else { 1 };
+                let to = match line == end.line {
+                    true => end.column,
+                    false => text.trim_end_matches(['\r', '\n']).chars().count() as u64 + 1,
+                };
+
+                let line_col = self.line_col(Location::new(line, from));
+                let end = self.line_col(Location::new(line, to)).col;
+                let param_overlap = |(p, len): &(LineCol, u32)| {
+                    p.line == line_col.line && p.col < end && line_col.col < p.col + len
+                };
+
+                if line_col.col < end && !params.iter().any(param_overlap) {
+                    let len = end - line_col.col;
+                    highlights.push(SqlHighlight {
+                        line_col,
+                        len,
+                        kind,
+                    });
+                }
+            }
+        }
+
+        highlights
+    }
+
+    /// formatted text of region content (indented like the first content line) or `None`
+    /// if it's invalid or already formatted
+    ///
+    /// content with `%%` escapes is not formatted: formatter splits them as operators
+    pub fn format(&self, options: sqlformat::FormatOptions) -> Option<String> {
+        let is_blank = self.text.trim().is_empty();
+        if is_blank || self.text.contains("%%") || !self.errors().is_empty() {
+            return None;
+        }
+
+        let sql = self.sql();
+        let interpolations = sql.match_indices(':').filter(|(pos, _)| {
+            // placeholders of interpolations only (same width as content)
+            self.text[*pos..].starts_with('%')
+        });
+        let params = interpolations.filter_map(|(pos, _)| {
+            let is_param_char = |c: char| c.is_alphanumeric() || matches!(c, '.' | '_' | '$');
+            let name = &sql[pos + 1..];
+            let name = &name[..name.find(|c| !is_param_char(c)).unwrap_or(name.len())];
+            (!name.is_empty()).then(|| (name.to_string(), format!("%{name}")))
+        });
+        let params = sqlformat::QueryParams::Named(params.collect());
+        let formatted = sqlformat::format(&sql, &params, options);
+
+        let first_line = self.text.lines().find(|l| !l.trim().is_empty())?;
+        let indent = &first_line[..first_line.len() - first_line.trim_start().len()];
+        let line_ending = if self.text.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let mut text = String::with_capacity(self.text.len());
+
+        for line in formatted.lines() {
+            if !line.trim().is_empty() {
+                text.push_str(indent);
+                text.push_str(line.trim_end());
+            }
+            text.push_str(line_ending);
+        }
+
+        (text != self.text).then_some(text)
+    }
+
+    /// content with `%ident` interpolations replaced by `:ident` placeholders and `%%`
+    /// escapes by single `%` (with the same width)
+    fn sql(&self) -> String {
+        let mut sql = String::with_capacity(self.text.len());
+        let mut chars = self.text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match (c, chars.peek()) {
+                ('%', Some('%')) => {
+                    chars.next();
+                    sql.push_str(" %");
+                }
+                ('%', Some(&n)) if n.is_alphanumeric() || n == '_' => sql.push(':'),
+                _ => sql.push(c),
+            }
+        }
+
+        sql
+    }
+
+    /// position and length of `%ident` interpolations
+    fn params(&self) -> Vec<(LineCol, u32)> {
+        let mut params = vec![];
+
+        for (line, text) in (self.lines.start..).zip(self.text.lines()) {
+            let mut col = 0;
+            let mut chars = text.chars().peekable();
+
+            while let Some(c) = chars.next() {
+                col += c.len_utf16() as u32;
+                match (c, chars.peek()) {
+                    ('%', Some('%')) => {
+                        chars.next();
+                        col += 1;
+                    }
+                    ('%', Some(&n)) if n.is_alphanumeric() || n == '_' => {
+                        let mut len = 1;
+                        while let Some(n) = chars.next_if(|&n| n.is_alphanumeric() || n == '_') {
+                            len += n.len_utf16() as u32;
+                        }
+                        params.push((LineCol::new(line, col - 1), len));
+                        col += len - 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        params
+    }
+
+    /// content line by sql location line (starts from 1)
+    fn line(&self, line: u64) -> Option<&str> {
+        self.text
+            .split_inclusive('\n')
+            .nth(line.checked_sub(1)? as usize)
+    }
+
+    /// document position of sql location (column starts from 1 and counts chars)
+    fn line_col(&self, location: Location) -> LineCol {
+        let line = self.lines.start + location.line.saturating_sub(1) as u32;
+        let text = self.line(location.line).unwrap_or("");
+        let chars = text
+            .chars()
+            .take(location.column.saturating_sub(1) as usize);
+        LineCol::new(line, chars.map(|c| c.len_utf16() as u32).sum())
+    }
+
+    /// location of the last char of content (errors at the end of input have no location)
+    fn end_location(&self) -> Location {
+        let lines = self.text.split_inclusive('\n').enumerate();
+        match lines.filter(|(_, l)| !l.trim().is_empty()).last() {
+            Some((idx, l)) => Location::new(idx as u64 + 1, l.trim_end().chars().count() as u64),
+            None => Location::new(1, 1),
+        }
+    }
+}
+
+fn is_keyword(keyword: Keyword) -> bool {
+    keywords::RESERVED_FOR_TABLE_ALIAS.contains(&keyword)
+        || keywords::RESERVED_FOR_COLUMN_ALIAS.contains(&keyword)
+        || KEYWORDS.contains(&keyword)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(text: &str) -> SqlRegion {
+        let lines = text.lines().count() as u32;
+        SqlRegion::new(0, 5..5 + lines, text.to_string())
+    }
+
+    fn params(region: &SqlRegion) -> Vec<(u32, u32, u32)> {
+        let highlights = region.highlights().into_iter();
+        let params = highlights.filter(|h| h.kind == SqlTokenKind::Parameter);
+        params
+            .map(|h| (h.line_col.line, h.line_col.col, h.len))
+            .collect()
+    }
+
+    #[test]
+    fn error_is_mapped_to_document_position() {
+        let r = region("  select a\n  from t whre a = %id\n");
+        let errors = r.errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span::new(LineCol::new(6, 14), 1));
+        assert_eq!(errors[0].message, "Expected: end of statement, found: a");
+
+        // error at the end of input is reported at the last char
+        let errors = region("  select a from\n\n").errors();
+        assert_eq!(errors[0].span.line_col, LineCol::new(5, 14));
+    }
+
+    #[test]
+    fn interpolation_columns_are_utf16() {
+        let r = region("  select 'ё😀', %id\n  where b = %b_2\n");
+        assert_eq!(params(&r), [(5, 16, 3), (6, 12, 4)]);
+        assert_eq!(r.sql(), "  select 'ё😀', :id\n  where b = :b_2\n");
+        assert!(r.errors().is_empty());
+    }
+
+    #[test]
+    fn escaped_percent_is_not_interpolation() {
+        let r = region("  select a from t where b like '%%x' and c = %c\n");
+        assert_eq!(r.sql(), "  select a from t where b like ' %x' and c = :c\n");
+        assert_eq!(params(&r), [(5, 45, 2)]);
+        assert!(r.errors().is_empty());
+        assert_eq!(r.format(sqlformat::FormatOptions::default()), None);
+    }
+}
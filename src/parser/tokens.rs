@@ -1,4 +1,5 @@
 use derive_more::{Constructor, From};
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug)]
 pub enum Token<'a> {
@@ -6,12 +7,58 @@ pub enum Token<'a> {
     IncludePath(StringLiteral<'a>),
     RegionOpen(Span),
     RegionClose(Span),
+    /// `#define`, `#ifdef`, `#ifndef`, `#else` or `#endif` line (without line ending)
+    Directive(RawToken<'a>),
     LineTerminator(LineCol),
     Common(RawToken<'a>),
     CommonWithLineEnding(RawToken<'a>),
     Eoi(LineCol),
 }
 
+impl<'a> Token<'a> {
+    pub fn line_col(&self) -> &LineCol {
+        match self {
+            Token::Include(s) | Token::RegionOpen(s) | Token::RegionClose(s) => &s.line_col,
+            Token::IncludePath(sl) => &sl.line_col,
+            Token::Directive(r) | Token::Common(r) | Token::CommonWithLineEnding(r) => &r.line_col,
+            Token::LineTerminator(lc) | Token::Eoi(lc) => lc,
+        }
+    }
+
+    /// moves token from `from` text to `to` text by line and byte offset (text
+    /// between token start line and token end should be equal in both texts)
+    pub fn rebase<'b>(
+        &self,
+        from: &str,
+        to: &'b str,
+        byte_delta: isize,
+        line_delta: i64,
+    ) -> Token<'b> {
+        let lc = |lc: &LineCol| LineCol::new((lc.line as i64 + line_delta) as u32, lc.col);
+        let span = |s: &Span| Span::new(lc(&s.line_col), s.len);
+        let text = |t: &str| {
+            let pos = (t.as_ptr() as usize - from.as_ptr() as usize) as isize + byte_delta;
+            &to[pos as usize..pos as usize + t.len()]
+        };
+
+        match self {
+            Token::Include(s) => Token::Include(span(s)),
+            Token::IncludePath(sl) => {
+                Token::IncludePath(StringLiteral::new(lc(&sl.line_col), text(sl.lit)))
+            }
+            Token::RegionOpen(s) => Token::RegionOpen(span(s)),
+            Token::RegionClose(s) => Token::RegionClose(span(s)),
+            Token::Directive(r) => Token::Directive(RawToken::new(lc(&r.line_col), text(r.text))),
+            Token::LineTerminator(l) => Token::LineTerminator(lc(l)),
+            Token::Common(r) => Token::Common(RawToken::new(lc(&r.line_col), text(r.text))),
+            Token::CommonWithLineEnding(r) => {
+                Token::CommonWithLineEnding(RawToken::new(lc(&r.line_col), text(r.text)))
+            }
+            Token::Eoi(l) => Token::Eoi(lc(l)),
+        }
+    }
+}
+
 #[derive(Debug, Constructor)]
 pub struct RawToken<'a> {
     pub line_col: LineCol,
@@ -26,13 +73,13 @@ pub struct StringLiteral<'a> {
     pub lit: &'a str,
 }
 
-#[derive(Debug, Constructor)]
+#[derive(Debug, Clone, PartialEq, Constructor, Serialize, Deserialize)]
 pub struct Span {
     pub line_col: LineCol,
     pub len: u32,
 }
 
-#[derive(Debug, From, Clone)]
+#[derive(Debug, From, Clone, PartialEq, Constructor, Serialize, Deserialize)]
 pub struct LineCol {
     pub line: u32,
     pub col: u32,
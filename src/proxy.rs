@@ -26,7 +26,9 @@ pub const JS_FILE_EXT: &str = ".js";
 pub const DECL_FILE_EXT: &str = ".d.ts";
 pub const PROXY_WORKSPACE: &str = "./.local/glproxy-workspace";
 pub const DEFAULT_SCRIPT_FILENAME: &str = "DEFAULT_INCLUDED.js";
+pub const JSCONFIG_FILENAME: &str = "jsconfig.json";
 pub const DEFAULT_TIMEOUT_MS: u64 = 5000;
+pub const DIAGNOSTIC_SOURCE: &str = "glscript";
 
 pub type ResFut<R> = BoxFuture<'static, Result<<R as Request>::Result, ResponseError>>;
 pub type ResReqProxy<R> = Result<<R as Request>::Result, ResponseError>;
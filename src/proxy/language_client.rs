@@ -7,6 +7,8 @@ use crate::proxy::{Error, Proxy, ResFut};
 mod apply_edit;
 mod publish_diagnostics;
 
+pub use publish_diagnostics::publish_syntax_diagnostics;
+
 pub fn init_language_client_router(proxy: Proxy) -> Router<Proxy> {
     let mut router: Router<Proxy> = Router::new(proxy);
     router
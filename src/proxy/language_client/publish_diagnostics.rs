@@ -1,12 +1,18 @@
 use std::collections::{HashMap, HashSet};
 
-use async_lsp::LanguageClient;
 use async_lsp::lsp_types::{self as lsp};
+use async_lsp::{ClientSocket, LanguageClient};
 use derive_more::Constructor;
 
-use crate::builder::EMIT_FILE_EXT;
-use crate::proxy::{DECL_FILE_EXT, Error, NotifyResult, Proxy, forward_build_range};
-use crate::types::{SCRIPT_IDENTIFIER_PREFIX, Source};
+use crate::builder::{Build, EMIT_FILE_EXT, IncludeIssueKind};
+use crate::parser::{LineCol, SyntaxError};
+use crate::proxy::forward_build_range;
+use crate::proxy::{DECL_FILE_EXT, DIAGNOSTIC_SOURCE, Error, NotifyResult, Proxy};
+use crate::state::State;
+use crate::types::{Document, SCRIPT_IDENTIFIER_PREFIX, Source};
+
+const UNRESOLVED_INCLUDE_CODE: &str = "unresolved-include";
+const INACTIVE_BRANCH_CODE: &str = "inactive-branch";
 
 #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
 pub fn proxy_publish_diagnostics(
@@ -34,6 +40,11 @@ pub fn proxy_publish_diagnostics(
     let mut source_diagnostics: Vec<lsp::Diagnostic> = Vec::new();
     type NS = lsp::NumberOrString;
 
+    // references of unchecked interpolations only resolve requests on them
+    let unchecked_references = (state.get_interpolation_references(&doc, true).into_iter())
+        .filter_map(|r| (!r.checked).then_some(r.range))
+        .collect::<HashSet<_>>();
+
     let forwarded_diagnostics: Vec<_> = params.diagnostics.into_par_iter().filter_map(|d| {
             let mut range = d.range;
             let Ok(source_of_diagnostic) = forward_build_range(&mut range, &doc_build) else {
@@ -55,6 +66,7 @@ pub fn proxy_publish_diagnostics(
                     // "7006" /* any type */ => return None,
                     "2300" /* duplicate identifier */ if d.message.contains(SCRIPT_IDENTIFIER_PREFIX) => return None,
                     "80002" /* recommend class decl */ => return None,
+                    "2304" | "2552" /* cannot find name */ if unchecked_references.contains(&range) => return None,
                     "2304" /* cannot find name */ => Some(DS::WARNING),
                     "2364" /* assignment err */ => Some(DS::ERROR),
                     "2551" /* similar ident */ => Some(DS::INFORMATION),
@@ -192,18 +204,188 @@ pub fn proxy_publish_diagnostics(
         source_diagnostics.extend(doc_diagnostics);
     }
 
+    source_diagnostics.extend(inactive_branch_diagnostics(&doc, &state));
+    source_diagnostics.extend(unresolved_include_diagnostics(&doc, &state));
+    source_diagnostics.extend(include_issue_diagnostics(&doc, &doc_build, &state));
+
+    let syntax = syntax_diagnostics(&doc, &state);
+    publish(&mut client, &state, &doc, source_diagnostics, syntax);
+
+    std::ops::ControlFlow::Continue(())
+}
+
+/// publish syntax diagnostics of changed document if they differ from published ones
+/// (other diagnostics are published again, moved by changes, until TSServer publishes
+/// diagnostics of build)
+pub fn publish_syntax_diagnostics(this: &mut Proxy, source_uri: &lsp::Url) {
+    let state = this.state.clone();
+    let Ok(doc) = state.get_doc(source_uri) else {
+        return;
+    };
+
+    let syntax = syntax_diagnostics(&doc, &state);
+    let (other, published_syntax) = state.get_published_diagnostics(&doc);
+    if syntax != published_syntax {
+        publish(&mut this.client(), &state, &doc, other, syntax);
+    }
+}
+
+fn publish(
+    client: &mut ClientSocket,
+    state: &State,
+    doc: &Document,
+    other: Vec<lsp::Diagnostic>,
+    syntax: Vec<lsp::Diagnostic>,
+) {
     let doc_uri = state.path_to_uri(&doc.path).unwrap();
     let doc_uri = (*doc_uri).clone();
+    let diagnostics = other.iter().chain(syntax.iter()).cloned().collect();
+    state.set_published_diagnostics(doc, other, syntax);
 
     client
         .publish_diagnostics(lsp::PublishDiagnosticsParams::new(
             doc_uri,
-            source_diagnostics,
+            diagnostics,
             None,
         ))
         .unwrap();
+}
 
-    std::ops::ControlFlow::Continue(())
+/// glscript directives errors reported by parser and by evaluation of conditionals, syntax
+/// errors of `#sql` regions
+fn syntax_diagnostics(doc: &Document, state: &State) -> Vec<lsp::Diagnostic> {
+    let to_diagnostic = |e: &SyntaxError| {
+        let start = lsp::Position::new(e.span.line_col.line, e.span.line_col.col);
+        let end = lsp::Position::new(start.line, start.character + e.span.len);
+
+        lsp::Diagnostic {
+            range: lsp::Range::new(start, end),
+            severity: Some(lsp::DiagnosticSeverity::ERROR),
+            code: Some(lsp::NumberOrString::String(e.kind.code().to_string())),
+            source: Some(DIAGNOSTIC_SOURCE.to_string()),
+            message: e.message.clone(),
+            ..Default::default()
+        }
+    };
+
+    let conditionals = state.get_conditionals(doc);
+    let sql_regions = doc.sql_regions().into_iter();
+    let sql_regions = sql_regions.filter(|r| !conditionals.is_inactive(r.open_idx));
+    let sql_errors = sql_regions.flat_map(|r| r.errors()).collect::<Vec<_>>();
+    let errors = doc.parse.errors.iter().chain(conditionals.errors.iter());
+    errors.chain(sql_errors.iter()).map(to_diagnostic).collect()
+}
+
+/// inactive branches of conditional directives are greyed out by client
+fn inactive_branch_diagnostics(doc: &Document, state: &State) -> Vec<lsp::Diagnostic> {
+    let conditionals = state.get_conditionals(doc);
+    let lines = conditionals.inactive_lines.into_iter();
+    let to_diagnostic = |lines: std::ops::Range<u32>| lsp::Diagnostic {
+        range: lsp::Range::new(
+            lsp::Position::new(lines.start, 0),
+            lsp::Position::new(lines.end, 0),
+        ),
+        severity: Some(lsp::DiagnosticSeverity::HINT),
+        code: Some(lsp::NumberOrString::String(
+            INACTIVE_BRANCH_CODE.to_string(),
+        )),
+        source: Some(DIAGNOSTIC_SOURCE.to_string()),
+        message: "inactive conditional branch".to_string(),
+        tags: Some(vec![lsp::DiagnosticTag::UNNECESSARY]),
+        ..Default::default()
+    };
+
+    lines.filter(|l| !l.is_empty()).map(to_diagnostic).collect()
+}
+
+fn unresolved_include_diagnostics(doc: &Document, state: &State) -> Vec<lsp::Diagnostic> {
+    let to_diagnostic = |(range, lit): (lsp::Range, String)| lsp::Diagnostic {
+        range,
+        severity: Some(lsp::DiagnosticSeverity::ERROR),
+        code: Some(lsp::NumberOrString::String(
+            UNRESOLVED_INCLUDE_CODE.to_string(),
+        )),
+        source: Some(DIAGNOSTIC_SOURCE.to_string()),
+        message: format!("cannot resolve '{lit}'"),
+        ..Default::default()
+    };
+
+    let unresolved = state.get_unresolved_includes(doc);
+    unresolved.into_iter().map(to_diagnostic).collect()
+}
+
+/// cycles back to the root document and redundant includes of the root document
+fn include_issue_diagnostics(doc: &Document, build: &Build, state: &State) -> Vec<lsp::Diagnostic> {
+    let project = state.get_project();
+    let site_range = |(_, lc, len): &(Source, LineCol, usize)| {
+        let start = lsp::Position::new(lc.line, lc.col);
+        lsp::Range::new(start, lsp::Position::new(lc.line, lc.col + *len as u32 + 2))
+    };
+    let location = |source: &Source, range| {
+        let uri = state.path_to_uri(&project.join(source.as_str())).ok()?;
+        Some(lsp::Location::new((*uri).clone(), range))
+    };
+
+    let mut diagnostics = vec![];
+
+    for issue in build.include_issues.iter() {
+        let diagnostic = match issue.kind {
+            IncludeIssueKind::Cycle if issue.target == *doc.source && issue.stack[0].2 != 0 => {
+                let chain = (issue.stack.iter())
+                    .map(|(s, _, _)| s.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" -> ");
+
+                let related_information = (issue.stack.iter().enumerate().skip(1))
+                    .filter_map(|(i, site)| {
+                        let location = location(&issue.stack[i - 1].0, site_range(site))?;
+                        let message = format!("includes '{}'", site.0);
+                        Some(lsp::DiagnosticRelatedInformation { location, message })
+                    })
+                    .collect();
+
+                lsp::Diagnostic {
+                    range: site_range(&issue.stack[0]),
+                    message: format!("include cycle: {} -> {chain}", doc.source),
+                    related_information: Some(related_information),
+                    ..Default::default()
+                }
+            }
+            IncludeIssueKind::Redundant if issue.includer == *doc.source => {
+                let first = state.get_source_stack(build, &issue.target);
+                let related_information = first.and_then(|first| {
+                    let site = first.last().filter(|site| site.2 != 0)?;
+                    let parent = match first.len() {
+                        1 => &doc.source,
+                        len => &first[len - 2].0,
+                    };
+                    let location = location(parent, site_range(site))?;
+                    let message = "already included here".to_string();
+                    Some(vec![lsp::DiagnosticRelatedInformation {
+                        location,
+                        message,
+                    }])
+                });
+
+                lsp::Diagnostic {
+                    range: site_range(issue.stack.last().unwrap()),
+                    message: format!("'{}' is already included", issue.target),
+                    tags: Some(vec![lsp::DiagnosticTag::UNNECESSARY]),
+                    related_information,
+                    ..Default::default()
+                }
+            }
+            _ => continue,
+        };
+
+        diagnostics.push(lsp::Diagnostic {
+            severity: Some(lsp::DiagnosticSeverity::WARNING),
+            source: Some(DIAGNOSTIC_SOURCE.to_string()),
+            ..diagnostic
+        });
+    }
+
+    diagnostics
 }
 
 #[derive(Constructor, Debug)]
@@ -211,3 +393,78 @@ struct DiagnosticEntry {
     source: Source,
     diagnostic: lsp::Diagnostic,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::state::testing::TestProject;
+
+    fn range(line: u32, start: u32, end: u32) -> lsp::Range {
+        lsp::Range::new(
+            lsp::Position::new(line, start),
+            lsp::Position::new(line, end),
+        )
+    }
+
+    #[test]
+    fn include_cycles_and_redundant_includes_are_reported() {
+        let files = [
+            (
+                "a.js",
+                "#include <b.js>\n#include <c.js>\n  #include <c.js>\n",
+            ),
+            ("b.js", "var b = 1;\n#include <a.js>\n"),
+            ("c.js", ""),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        let bundle = project.bundle("a.js");
+        let source = |path: &str| Source::new(path.into());
+
+        let issues = bundle.include_issues.iter();
+        let issues = issues.map(|i| (i.kind, i.includer.as_str(), i.target.as_str()));
+        assert_eq!(
+            issues.collect::<Vec<_>>(),
+            [
+                (IncludeIssueKind::Cycle, "b.js", "a.js"),
+                (IncludeIssueKind::Redundant, "a.js", "c.js")
+            ]
+        );
+
+        let cycle_stack = &bundle.include_issues[0].stack;
+        let expected = [
+            (source("b.js"), (0, 9).into(), 4),
+            (source("a.js"), (1, 9).into(), 4),
+        ];
+        assert_eq!(cycle_stack, &expected);
+        let redundant_stack = &bundle.include_issues[1].stack;
+        assert_eq!(redundant_stack, &[(source("c.js"), (2, 11).into(), 4)]);
+
+        let doc = project.state.get_doc(&project.uri("a.js")).unwrap();
+        let diagnostics = include_issue_diagnostics(&doc, &bundle, &project.state);
+        assert_eq!(diagnostics.len(), 2);
+
+        let cycle = &diagnostics[0];
+        assert_eq!(cycle.range, range(0, 9, 15));
+        assert_eq!(cycle.message, "include cycle: a.js -> b.js -> a.js");
+        let related = cycle.related_information.as_ref().unwrap();
+        let location = lsp::Location::new(project.uri("b.js"), range(1, 9, 15));
+        assert_eq!(
+            related.iter().map(|r| &r.location).collect::<Vec<_>>(),
+            [&location]
+        );
+        assert_eq!(cycle.tags, None);
+
+        let redundant = &diagnostics[1];
+        assert_eq!(redundant.range, range(2, 11, 17));
+        assert_eq!(redundant.message, "'c.js' is already included");
+        assert_eq!(redundant.tags, Some(vec![lsp::DiagnosticTag::UNNECESSARY]));
+        let related = redundant.related_information.as_ref().unwrap();
+        let location = lsp::Location::new(project.uri("a.js"), range(1, 9, 15));
+        assert_eq!(
+            related.iter().map(|r| &r.location).collect::<Vec<_>>(),
+            [&location]
+        );
+    }
+}
@@ -10,8 +10,10 @@ mod common_features;
 mod completion;
 mod definition;
 mod doc_sync;
+mod document_link;
 mod formatting;
 mod hover;
+mod include_tree;
 mod inlay_hint;
 mod lifecycle;
 mod references;
@@ -32,6 +34,13 @@ pub fn definition_params(uri: Uri, pos: lsp::Position) -> lsp::GotoDefinitionPar
     }
 }
 
+/// contents of bundle file on disk (bundle content is synced with tsserver by notifications)
+pub const BUNDLE_FILE_CONTENTS: &str =
+    "// DO NOT EDIT/DELETE THIS FILE. Need for correct tsservice & glproxy work";
+
+/// completion of `%ident` interpolations in strings and regions (resolved by proxy)
+pub const INTERPOLATION_TRIGGER_CHARACTER: &str = "%";
+
 pub fn did_open(
     s: &mut ServerSocket,
     uri: &Uri,
@@ -99,7 +108,9 @@ pub fn init_language_server_router(proxy: Proxy) -> Router<Proxy> {
         .request::<R::InlayHintRequest, _>(inlay_hint::proxy_inlay_hint)
         .request::<R::CodeActionRequest, _>(code_action::proxy_code_action)
         .request::<R::ExecuteCommand, _>(code_action::proxy_execute_command)
-        .request::<R::DocumentHighlightRequest, _>(common_features::proxy_document_highlight);
+        .request::<R::DocumentHighlightRequest, _>(common_features::proxy_document_highlight)
+        .request::<R::DocumentLinkRequest, _>(document_link::proxy_document_link)
+        .request::<include_tree::IncludeTreeRequest, _>(include_tree::proxy_include_tree);
     router
 }
 
@@ -5,7 +5,7 @@ use async_lsp::{LanguageServer, lsp_types as lsp};
 
 use crate::builder::Build;
 use crate::parser::Token;
-use crate::proxy::{Error, Proxy, ResFut};
+use crate::proxy::{DIAGNOSTIC_SOURCE, Error, Proxy, ResFut};
 use crate::state::State;
 use crate::try_ensure_bundle;
 use crate::types::Document;
@@ -22,6 +22,7 @@ pub fn proxy_code_action(
         .is_some_and(|k| lsp::CodeActionTriggerKind::AUTOMATIC == k)
     {
         // client send recoursive req sequence (code_action -> publish_diagnostics -> code_action...)
+        // and include suggestions walk project scripts, so actions are resolved on invoke only
         return Box::pin(async move { Ok(None) });
     };
 
@@ -30,6 +31,7 @@ pub fn proxy_code_action(
     let bundle = try_ensure_bundle!(this, uri, params, code_action);
     let st = this.state.clone();
     let doc = st.get_doc(uri).unwrap();
+    let include_actions = get_include_quick_fix_actions(&doc, &params, &st);
     let transpile = st.get_transpile(uri).unwrap();
     let Some(mut bundle_range) = bundle.forward_src_range(&params.range, &doc.source) else {
         return Box::pin(async move { Err(Error::forward_failed()) });
@@ -39,9 +41,12 @@ pub fn proxy_code_action(
     if let Some(source_start) = first_non_include_build_pos
         && source_start > bundle_range.end
     {
-        return match get_transpile_to_es_syntax_action(&doc, &transpile, &st) {
-            Some(transpile_action) => Box::pin(async move { Ok(Some(vec![transpile_action])) }),
-            None => Box::pin(async move { Ok(None) }),
+        let mut actions = include_actions;
+        actions.extend(get_transpile_to_es_syntax_action(&doc, &transpile, &st));
+
+        return match actions.is_empty() {
+            false => Box::pin(async move { Ok(Some(actions)) }),
+            true => Box::pin(async move { Ok(None) }),
         };
     }
 
@@ -81,8 +86,10 @@ pub fn proxy_code_action(
                     actions.push(transpile_action);
                 };
 
+                actions.splice(0..0, include_actions);
                 actions
             })),
+            Ok(None) if !include_actions.is_empty() => Ok(Some(include_actions)),
             Ok(None) => Ok(None),
             Err(err) => {
                 tracing::warn!("tsserer error: {err}");
@@ -101,6 +108,51 @@ pub fn proxy_execute_command(
     Box::pin(async move { s.execute_command(params).await.map_err(Error::internal) })
 }
 
+/// replace unresolved include path literal with nearest existing project files
+fn get_include_quick_fix_actions(
+    doc: &Document,
+    params: &lsp::CodeActionParams,
+    st: &State,
+) -> Vec<lsp::CodeActionOrCommand> {
+    let mut actions = vec![];
+    let doc_uri = st.path_to_uri(&doc.path).unwrap();
+    let doc_uri = (*doc_uri).clone();
+    let r = &params.range;
+
+    for (range, lit) in st.get_unresolved_includes(doc) {
+        if range.end < r.start || r.end < range.start {
+            continue;
+        }
+
+        let diagnostics = (params.context.diagnostics.iter())
+            .filter(|d| d.range == range && d.source.as_deref() == Some(DIAGNOSTIC_SOURCE))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let lit_range = lsp::Range::new(
+            lsp::Position::new(range.start.line, range.start.character + 1),
+            lsp::Position::new(range.end.line, range.end.character - 1),
+        );
+
+        let suggestions = st.suggest_include_paths(&doc.path, &lit);
+        for (i, suggestion) in suggestions.into_iter().enumerate() {
+            let edit = lsp::TextEdit::new(lit_range, suggestion.clone());
+            let changes = HashMap::from([(doc_uri.clone(), vec![edit])]);
+
+            actions.push(lsp::CodeActionOrCommand::CodeAction(lsp::CodeAction {
+                title: format!("Change include to '{suggestion}'"),
+                kind: K::QUICKFIX.into(),
+                diagnostics: Some(diagnostics.clone()),
+                is_preferred: (i == 0).into(),
+                edit: lsp::WorkspaceEdit::new(changes).into(),
+                ..Default::default()
+            }));
+        }
+    }
+
+    actions
+}
+
 fn get_transpile_to_es_syntax_action(
     doc: &Document,
     transpile: &Build,
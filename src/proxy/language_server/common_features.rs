@@ -73,11 +73,19 @@ pub fn proxy_prepare_rename(
         if doc.is_inside_include_path(&params.position) {
             return Ok(None);
         };
+        // `%ident` interpolation is renamed with identifier which it refers to
+        let interpolation = doc.get_interpolation(&params.position);
+        if let Some(target) = doc.interpolation_target(&params.position) {
+            params.position = target;
+        }
         try_forward_text_document_position_params!(state, bundle, params);
         let mut res = s.prepare_rename(params).await.map_err(Error::internal);
         if let Ok(Some(lsp::PrepareRenameResponse::Range(ref mut r))) = res {
             forward_build_range(r, &bundle)?;
         }
+        if let (Ok(Some(_)), Some((range, _))) = (&res, interpolation) {
+            return Ok(Some(lsp::PrepareRenameResponse::Range(range)));
+        }
         res
     })
 }
@@ -1,28 +1,225 @@
+use std::collections::HashSet;
 use std::sync::Arc;
 
+use async_lsp::lsp_types::Url as Uri;
 use async_lsp::lsp_types::request as R;
 use async_lsp::{LanguageServer, lsp_types as lsp};
 
 use crate::builder::Build;
-use crate::proxy::{Proxy, ResFut, language_server::Error};
-use crate::state::State;
-use crate::types::SCRIPT_IDENTIFIER_PREFIX;
+use crate::parser::{DeclarationKind, Token};
+use crate::proxy::language_server::{Error, INTERPOLATION_TRIGGER_CHARACTER};
+use crate::proxy::{JS_FILE_EXT, Proxy, ResFut};
+use crate::state::{State, is_glob};
+use crate::types::{Document, SCRIPT_IDENTIFIER_PREFIX};
 use crate::{try_ensure_bundle, try_ensure_transpile, try_forward_text_document_position_params};
 
 type Res = lsp::CompletionResponse;
 
+/// key of item data which marks item of not included declaration
+const INCLUDE_ITEM_DATA: &str = "glscriptInclude";
+/// key of item data with uri of requested document (its bundle resolves item)
+const SOURCE_ITEM_DATA: &str = "glscriptSource";
+const INCLUDE_ITEMS_LIMIT: usize = 100;
+
 #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
-pub fn proxy_completion(this: &mut Proxy, p: lsp::CompletionParams) -> ResFut<R::Completion> {
+pub fn proxy_completion(this: &mut Proxy, mut p: lsp::CompletionParams) -> ResFut<R::Completion> {
     let s = this.server();
     let uri = &p.text_document_position.text_document.uri;
     let b = try_ensure_bundle!(this, uri, p, completion);
     let t = try_ensure_transpile!(this, uri, p, completion);
     let st = this.state.clone();
     let doc = this.state.get_doc(uri).unwrap();
+    let interpolation_scope = doc.interpolation_scope(&p.text_document_position.position);
+    let trigger = p
+        .context
+        .as_ref()
+        .and_then(|c| c.trigger_character.as_deref());
+    let triggered_by_percent = trigger == Some(INTERPOLATION_TRIGGER_CHARACTER);
 
     Box::pin(async move {
-        let inside_include_path = doc.is_inside_include_path(&p.text_document_position.position);
-        get_completions(p, st, s, if inside_include_path { t } else { b }).await
+        match interpolation_scope {
+            Some(scope) => {
+                p.text_document_position.position = scope;
+                p.context = None; // synthesized position doesn't follow trigger character
+                let res = get_completions(p, st, s, b).await?;
+                return Ok(res.map(interpolation_completions));
+            }
+            None if triggered_by_percent => return Ok(None), // remainder operator
+            None => {}
+        }
+
+        let pos = &p.text_document_position.position;
+        let inside_include_path = doc.is_inside_include_path(pos);
+        let glob_item = glob_completion(&st, &doc, pos);
+        let include_items = match inside_include_path {
+            true => vec![],
+            false => include_completions(&st, &doc, &b, pos),
+        };
+        let build = if inside_include_path { t } else { b };
+        let res = get_completions(p, st, s, build).await?;
+        let res = with_include_completions(res, include_items);
+
+        Ok(match (res, glob_item) {
+            (Some(Res::Array(mut items)), Some(item)) => Some(Res::Array({
+                items.push(item);
+                items
+            })),
+            (Some(Res::List(mut list)), Some(item)) => Some(Res::List({
+                list.items.push(item);
+                list
+            })),
+            (None, Some(item)) => Some(Res::Array(vec![item])),
+            (res, None) => res,
+        })
+    })
+}
+
+/// identifiers in scope of literal which are inserted after `%` as is
+fn interpolation_completions(res: Res) -> Res {
+    let as_ident = |item: &mut lsp::CompletionItem| {
+        item.insert_text = None;
+        item.insert_text_format = None;
+        item.text_edit = None; // edits of synthesized position
+        item.additional_text_edits = None;
+        let is_ident_char = |c: char| c.is_alphanumeric() || c == '_';
+        item.kind != Some(lsp::CompletionItemKind::KEYWORD) && item.label.chars().all(is_ident_char)
+    };
+
+    match res {
+        Res::Array(mut items) => Res::Array({
+            items.retain_mut(as_ident);
+            items
+        }),
+        Res::List(mut list) => Res::List({
+            list.items.retain_mut(as_ident);
+            list
+        }),
+    }
+}
+
+/// top-level declarations (matched by typed identifier) of project scripts which are not
+/// included yet: accepted item includes its script after the last include statement
+fn include_completions(
+    state: &State,
+    doc: &Document,
+    bundle: &Build,
+    pos: &lsp::Position,
+) -> Vec<lsp::CompletionItem> {
+    let is_ident_char = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+    let Some(line) = doc.buffer.get_line(pos.line as usize) else {
+        return vec![];
+    };
+    let typed = line
+        .chars()
+        .take(pos.character as usize)
+        .collect::<String>();
+    let prefix = typed.trim_end_matches(is_ident_char);
+    let ident = &typed[prefix.len()..];
+    let Some(include_pos) = doc.first_non_include_pos() else {
+        return vec![];
+    };
+
+    // declarations are not members
+    if ident.is_empty() || prefix.trim_end().ends_with('.') {
+        return vec![];
+    }
+
+    let has_includes =
+        (doc.parse.compressed_tokens.iter()).any(|t| matches!(t, Token::IncludePath(_)));
+
+    let mut declarations = state.find_declarations(ident, &state.get_bundle_sources(bundle));
+    declarations.sort_unstable_by(|a, b| a.1.name.cmp(&b.1.name).then_with(|| a.0.cmp(&b.0)));
+
+    declarations
+        .into_iter()
+        .map(|(literal, d)| {
+            let include = format!("#include <{literal}>");
+            let new_text = match has_includes {
+                true => format!("\n{include}"),
+                false => format!("{include}\n"),
+            };
+            let edit = lsp::TextEdit::new(lsp::Range::new(include_pos, include_pos), new_text);
+
+            lsp::CompletionItem {
+                label: d.name,
+                kind: Some(match d.kind {
+                    DeclarationKind::Function => lsp::CompletionItemKind::FUNCTION,
+                    DeclarationKind::Class => lsp::CompletionItemKind::CLASS,
+                    DeclarationKind::Variable => lsp::CompletionItemKind::VARIABLE,
+                }),
+                label_details: Some(lsp::CompletionItemLabelDetails {
+                    detail: None,
+                    description: Some(literal.clone()),
+                }),
+                detail: Some(include),
+                sort_text: Some("~".into()), // after declarations of bundle
+                additional_text_edits: Some(vec![edit]),
+                data: Some(serde_json::json!({ INCLUDE_ITEM_DATA: literal })),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// appends include items which are not shadowed by tsserver items
+fn with_include_completions(
+    res: Option<Res>,
+    include_items: Vec<lsp::CompletionItem>,
+) -> Option<Res> {
+    if include_items.is_empty() {
+        return res;
+    }
+
+    let mut list = match res {
+        Some(Res::Array(items)) => lsp::CompletionList {
+            is_incomplete: false,
+            items,
+        },
+        Some(Res::List(list)) => list,
+        None => lsp::CompletionList::default(),
+    };
+
+    let labels = list
+        .items
+        .iter()
+        .map(|i| i.label.clone())
+        .collect::<HashSet<_>>();
+    let include_items = include_items
+        .into_iter()
+        .filter(|i| !labels.contains(&i.label));
+    let include_items = include_items.collect::<Vec<_>>();
+
+    // items are matched by identifier typed at request
+    list.is_incomplete |= include_items.len() > INCLUDE_ITEMS_LIMIT;
+    list.items
+        .extend(include_items.into_iter().take(INCLUDE_ITEMS_LIMIT));
+    Some(Res::List(list))
+}
+
+/// `*.js` glob item which includes all scripts of typed include path directory
+fn glob_completion(
+    state: &State,
+    doc: &Document,
+    pos: &lsp::Position,
+) -> Option<lsp::CompletionItem> {
+    let sl = doc.get_include_path(pos)?;
+    let typed = pos.character.checked_sub(sl.line_col.col + 1)?;
+    let dir = sl.lit.chars().take(typed as usize).collect::<String>();
+
+    if !(dir.is_empty() || dir.ends_with('/')) || is_glob(&dir) {
+        return None;
+    }
+
+    let pattern = format!("{dir}*{JS_FILE_EXT}");
+    let matched = state.resolve_include_paths(&doc.path, &pattern);
+    let count = matched.iter().filter(|p| p.is_file()).count();
+
+    (count > 0).then(|| lsp::CompletionItem {
+        label: format!("*{JS_FILE_EXT}"),
+        kind: Some(lsp::CompletionItemKind::FILE),
+        detail: Some(format!("include all {count} scripts of directory")),
+        sort_text: Some("0".into()),
+        ..Default::default()
     })
 }
 
@@ -34,6 +231,9 @@ fn get_completions(
 ) -> ResFut<R::Completion> {
     Box::pin(async move {
         let doc_pos = &mut params.text_document_position;
+        let doc_uri = doc_pos.text_document.uri.clone();
+        let doc = state.get_doc(&doc_uri).unwrap();
+        let header_end = doc.first_non_include_build_pos(&build);
         let f = |mut item: lsp::CompletionItem| {
             if item.label.starts_with(SCRIPT_IDENTIFIER_PREFIX) {
                 return None;
@@ -50,7 +250,8 @@ fn get_completions(
                 }
                 _ => {}
             };
-            forward(&mut item);
+            forward(&mut item, Some((&doc, &build)), header_end);
+            with_source_data(&mut item, &doc_uri);
             Some(item)
         };
 
@@ -77,22 +278,189 @@ fn get_completions(
 
 pub fn proxy_completion_item_resolve(
     this: &mut Proxy,
-    params: lsp::CompletionItem,
+    mut params: lsp::CompletionItem,
 ) -> ResFut<R::ResolveCompletionItem> {
     let mut s = this.server();
+    let st = this.state.clone();
+    let is_include_item =
+        (params.data.as_ref()).is_some_and(|d| d.get(INCLUDE_ITEM_DATA).is_some());
+    if is_include_item {
+        return Box::pin(async move { Ok(params) }); // resolved by proxy
+    }
+
+    let uri = take_source_data(&mut params);
     Box::pin(async move {
         s.completion_item_resolve(params)
             .await
             .map_err(Error::internal)
             .map(|mut res| {
-                forward(&mut res);
+                let doc = uri.as_ref().and_then(|uri| st.get_doc(uri).ok());
+                let bundle = uri.as_ref().and_then(|uri| st.get_bundle(uri));
+                let target = doc.as_ref().zip(bundle.as_deref());
+                let header_end = target.and_then(|(doc, b)| doc.first_non_include_build_pos(b));
+                // text edit of item is already forwarded by completion request
+                forward_additional_edits(&mut res, target, header_end);
                 res
             })
     })
 }
 
-fn forward(item: &mut lsp::CompletionItem) {
-    item.text_edit = None; // can't define context
-    item.additional_text_edits = None;
-    item.command = None;
+/// marks item by `uri` of requested document (data of tsserver item is kept)
+fn with_source_data(item: &mut lsp::CompletionItem, uri: &Uri) {
+    let data = item.data.get_or_insert_with(|| serde_json::json!({}));
+    if let Some(data) = data.as_object_mut() {
+        data.insert(SOURCE_ITEM_DATA.into(), uri.as_str().into());
+    }
+}
+
+/// uri of requested document of item (item data is restored for tsserver)
+fn take_source_data(item: &mut lsp::CompletionItem) -> Option<Uri> {
+    let data = item.data.as_mut()?.as_object_mut()?;
+    let uri = data.remove(SOURCE_ITEM_DATA)?;
+    if data.is_empty() {
+        item.data = None;
+    }
+    Uri::parse(uri.as_str()?).ok()
+}
+
+/// maps range from build back to `doc`: ranges of other sources and of include statements
+/// before `header_end` (like auto-imports of tsserver) aren't mapped, all ranges aren't
+/// mapped if `header_end` isn't mapped to build
+fn forward_range(
+    range: &lsp::Range,
+    target: Option<(&Document, &Build)>,
+    header_end: Option<lsp::Position>,
+) -> Option<lsp::Range> {
+    let (doc, build) = target?;
+    if range.start < header_end? {
+        return None;
+    }
+    let (range, source) = build.forward_build_range(range)?;
+    (source == *doc.source).then_some(range)
+}
+
+/// maps edits of item from build back to `doc`, edits which aren't mapped are dropped
+fn forward(
+    item: &mut lsp::CompletionItem,
+    target: Option<(&Document, &Build)>,
+    header_end: Option<lsp::Position>,
+) {
+    let forward_range = |range: &lsp::Range| forward_range(range, target, header_end);
+    item.text_edit = match item.text_edit.take() {
+        Some(lsp::CompletionTextEdit::Edit(mut edit)) => forward_range(&edit.range).map(|r| {
+            edit.range = r;
+            lsp::CompletionTextEdit::Edit(edit)
+        }),
+        Some(lsp::CompletionTextEdit::InsertAndReplace(mut edit)) => {
+            match (forward_range(&edit.insert), forward_range(&edit.replace)) {
+                (Some(insert), Some(replace)) => {
+                    (edit.insert, edit.replace) = (insert, replace);
+                    Some(lsp::CompletionTextEdit::InsertAndReplace(edit))
+                }
+                _ => None,
+            }
+        }
+        None => None,
+    };
+
+    forward_additional_edits(item, target, header_end);
+}
+
+/// maps additional edits of item (like auto-imports of tsserver) from build back to `doc`
+fn forward_additional_edits(
+    item: &mut lsp::CompletionItem,
+    target: Option<(&Document, &Build)>,
+    header_end: Option<lsp::Position>,
+) {
+    let edits = item.additional_text_edits.take().into_iter().flatten();
+    let edits = edits.filter_map(|mut edit| {
+        edit.range = forward_range(&edit.range, target, header_end)?;
+        Some(edit)
+    });
+    let edits = edits.collect::<Vec<_>>();
+    item.additional_text_edits = (!edits.is_empty()).then_some(edits);
+    item.command = None; // can't define context
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::state::testing::TestProject;
+    use crate::types::Source;
+
+    fn edit_item(range: lsp::Range) -> lsp::CompletionItem {
+        let edit = lsp::TextEdit::new(range, "x".into());
+        lsp::CompletionItem {
+            text_edit: Some(lsp::CompletionTextEdit::Edit(edit.clone())),
+            additional_text_edits: Some(vec![edit]),
+            ..Default::default()
+        }
+    }
+
+    fn edit_range(item: &lsp::CompletionItem) -> Option<lsp::Range> {
+        match item.text_edit.as_ref()? {
+            lsp::CompletionTextEdit::Edit(edit) => Some(edit.range),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn edits_are_forwarded_to_document_only() {
+        let files = [
+            ("main.js", "#include <lib.js>\nvar abc = 1;\n"),
+            ("lib.js", "var lib = 2;\n"),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        let bundle = project.bundle("main.js");
+        let doc = project.state.get_doc(&project.uri("main.js")).unwrap();
+        let header_end = doc.first_non_include_build_pos(&bundle);
+        let target = Some((&doc, &bundle));
+        let build_range = |pos: lsp::Position, source: &str| {
+            let source = Source::new(source.into());
+            let start = bundle.forward_src_position(&pos, &source).unwrap();
+            let end = lsp::Position::new(start.line, start.character + 3);
+            lsp::Range::new(start, end)
+        };
+
+        // kept
+        let source_range = lsp::Range::new(lsp::Position::new(1, 4), lsp::Position::new(1, 7));
+        let mut item = edit_item(build_range(source_range.start, "main.js"));
+        forward(&mut item, target, header_end);
+        assert_eq!(edit_range(&item), Some(source_range));
+        assert_eq!(item.additional_text_edits.unwrap()[0].range, source_range);
+
+        // other source
+        let mut item = edit_item(build_range(lsp::Position::new(0, 4), "lib.js"));
+        forward(&mut item, target, header_end);
+        assert!(item.text_edit.is_none() && item.additional_text_edits.is_none());
+
+        // inside header
+        let start = lsp::Position::new(0, 0);
+        let mut item = edit_item(lsp::Range::new(start, start));
+        assert!(start < header_end.unwrap());
+        forward(&mut item, target, header_end);
+        assert!(item.text_edit.is_none() && item.additional_text_edits.is_none());
+
+        // text edit of resolved item is already forwarded
+        let mut item = edit_item(source_range);
+        item.additional_text_edits = None;
+        forward_additional_edits(&mut item, target, header_end);
+        assert_eq!(edit_range(&item), Some(source_range));
+    }
+
+    #[test]
+    fn source_data_is_restored_for_tsserver() {
+        let uri = Uri::parse("file:///main.js").unwrap();
+        let mut item = lsp::CompletionItem::default();
+        with_source_data(&mut item, &uri);
+        assert_eq!(take_source_data(&mut item), Some(uri.clone()));
+        assert_eq!(item.data, None);
+
+        item.data = Some(json!({ "file": "bundle.js" }));
+        with_source_data(&mut item, &uri);
+        assert_eq!(take_source_data(&mut item), Some(uri));
+        assert_eq!(item.data, Some(json!({ "file": "bundle.js" })));
+    }
 }
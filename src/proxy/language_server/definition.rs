@@ -39,11 +39,16 @@ where
     let mut service = this.server();
     let uri = &params.text_document_position_params.text_document.uri;
     let req_bundle = try_ensure_bundle!(this, uri, params, definition);
-    let req_bundle_sources = req_bundle.sources();
+    let req_bundle_sources = this.state.get_bundle_sources(&req_bundle);
     let state = this.state.clone();
+    let pos = &params.text_document_position_params.position;
+    let interpolation_target = state.get_doc(uri).unwrap().interpolation_target(pos);
 
     Box::pin(async move {
         let doc_pos = &mut params.text_document_position_params;
+        if let Some(target) = interpolation_target {
+            doc_pos.position = target;
+        }
         try_forward_text_document_position_params!(state, req_bundle, doc_pos);
 
         let res = call(&mut service, params).await.map_err(Error::internal);
@@ -1,10 +1,21 @@
+use std::collections::HashSet;
+
 use async_lsp::lsp_types::request as R;
 use async_lsp::{LanguageServer, lsp_types as lsp};
 
+use serde_json::json;
+
 use crate::builder::EMIT_FILE_EXT;
-use crate::proxy::language_server::{did_close, did_open};
-use crate::proxy::{Canonicalize, JS_LANG_ID, NotifyResult, Proxy, ResFut};
+use crate::proxy::language_client::publish_syntax_diagnostics;
+use crate::proxy::language_server::{BUNDLE_FILE_CONTENTS, did_close, did_open};
+use crate::proxy::{Canonicalize, Error, JS_FILE_EXT, JS_LANG_ID, NotifyResult, PROXY_WORKSPACE};
+use crate::proxy::{Proxy, ResFut};
+use crate::state::State;
 use crate::try_ensure_bundle;
+use crate::types::Document;
+
+/// client side command of vscode-like editors to show locations in peek view
+const SHOW_REFERENCES_COMMAND: &str = "editor.action.showReferences";
 
 pub fn proxy_did_open(this: &mut Proxy, params: lsp::DidOpenTextDocumentParams) -> NotifyResult {
     let s = &mut this.server();
@@ -33,13 +44,32 @@ pub fn proxy_did_open(this: &mut Proxy, params: lsp::DidOpenTextDocumentParams)
             return std::ops::ControlFlow::Continue(());
         };
 
+        publish_syntax_diagnostics(this, uri);
         let b = this.state.set_bundle(&doc.uri).unwrap();
         let t = this.state.set_transpile(&doc.uri).unwrap();
 
-        let contents = "// DO NOT EDIT/DELETE THIS FILE. Need for correct tsservice & glproxy work";
-        std::fs::write(b.build.uri.to_file_path().unwrap(), contents).unwrap();
-
-        let _ = did_open(s, &b.build.uri, &b.build.content, b.version.into());
+        std::fs::write(b.build.uri.to_file_path().unwrap(), BUNDLE_FILE_CONTENTS).unwrap();
+
+        match this.state.get_shared_prelude() {
+            // shared prelude is already opened on initialization
+            Some(prelude) if prelude.uri == b.build.uri => {
+                let text_document =
+                    lsp::VersionedTextDocumentIdentifier::new(b.build.uri.clone(), b.version);
+                let content_changes = vec![lsp::TextDocumentContentChangeEvent {
+                    text: b.build.content.clone(),
+                    range_length: None,
+                    range: None,
+                }];
+                let change = lsp::DidChangeTextDocumentParams {
+                    text_document,
+                    content_changes,
+                };
+                let _ = s.did_change(change);
+            }
+            _ => {
+                let _ = did_open(s, &b.build.uri, &b.build.content, b.version.into());
+            }
+        }
         let _ = did_open(s, &t.build.uri, &t.build.content, t.version.into());
 
         // fallback if code_lens not supported by client
@@ -75,8 +105,14 @@ pub fn proxy_did_change(
     let hash_new = st.get_doc(uri).unwrap().transpile_hash;
     let transpile_changed = hash_prev != hash_new;
 
-    // 2. forward params into language server
-    let bundles = st.get_bundles_contains_source(&doc.source);
+    // 2. forward params into language server (includes of shared prelude decide which
+    // sources are skipped by every bundle)
+    let prelude = st.get_shared_prelude();
+    let bundles =
+        match prelude.is_some_and(|p| transpile_changed && p.sources().contains(&doc.source)) {
+            true => st.get_bundles(),
+            false => st.get_bundles_contains_source(&doc.source),
+        };
     for doc_path in bundles {
         let params = params.clone();
         st.add_changes(doc_path, params, transpile_changed);
@@ -84,6 +120,7 @@ pub fn proxy_did_change(
 
     // 3. commit req doc
     st.commit_changes(uri, &mut service);
+    publish_syntax_diagnostics(this, uri);
 
     // fallback if code_lens not supported by client
     if st.get_current_doc() != Some(uri.try_canonicalize()) {
@@ -107,10 +144,17 @@ pub fn proxy_did_close(this: &mut Proxy, params: lsp::DidCloseTextDocumentParams
         return std::ops::ControlFlow::Continue(());
     };
 
-    let _ = did_close(&mut this.server(), &bundle.uri);
-    let _ = std::fs::remove_file(bundle.uri.to_file_path().unwrap());
+    // shared prelude stays opened for other bundles
+    if this
+        .state
+        .get_shared_prelude()
+        .is_none_or(|p| p.uri != bundle.uri)
+    {
+        let _ = did_close(&mut this.server(), &bundle.uri);
+        let _ = std::fs::remove_file(bundle.uri.to_file_path().unwrap());
 
-    this.state.remove_bundle(uri);
+        this.state.remove_bundle(uri);
+    }
 
     let Some(doc) = this.state.get_transpile(uri) else {
         return std::ops::ControlFlow::Continue(());
@@ -126,6 +170,10 @@ pub fn proxy_did_change_watched_files(
     this: &mut Proxy,
     mut params: lsp::DidChangeWatchedFilesParams,
 ) -> NotifyResult {
+    for change in params.changes.iter() {
+        sync_unopened_doc(&this.state, change);
+    }
+
     let mut forward_changes = Vec::with_capacity(params.changes.len());
     for channge in params.changes {
         let is_emit_file = !channge.uri.as_str().ends_with(EMIT_FILE_EXT);
@@ -148,6 +196,50 @@ pub fn proxy_did_change_watched_files(
     std::ops::ControlFlow::Continue(())
 }
 
+/// keeps documents which are not opened by client (and include graph) in sync with disk
+fn sync_unopened_doc(state: &State, change: &lsp::FileEvent) {
+    let uri = &change.uri;
+    let Ok(path) = uri.to_file_path() else {
+        return;
+    };
+
+    let is_js = uri.path().ends_with(JS_FILE_EXT) && !uri.path().ends_with(EMIT_FILE_EXT);
+    let proxy_ws = state.get_project().join(PROXY_WORKSPACE);
+    if !is_js || path.starts_with(proxy_ws) {
+        return;
+    }
+
+    // created or deleted script could be matched by glob includes or change root which
+    // include is resolved by
+    if change.typ != lsp::FileChangeType::CHANGED {
+        state.clear_include_paths_caches();
+        state.outdate_includes_by_script(&path);
+    }
+
+    if state.get_bundle(uri).is_some() {
+        return;
+    }
+
+    if change.typ == lsp::FileChangeType::DELETED {
+        state.remove_doc(uri);
+        return;
+    }
+
+    let Ok(text) = std::fs::read(&path).map(|b| String::from_utf8_lossy(&b).into_owned()) else {
+        return;
+    };
+
+    let change = lsp::TextDocumentContentChangeEvent {
+        text,
+        range_length: None,
+        range: None,
+    };
+
+    if let Err(err) = state.set_doc(uri, &[change]) {
+        tracing::warn!("sync watched file {path:?} failed: {err}");
+    }
+}
+
 pub fn proxy_sync_doc_by_code_lens_request(
     this: &mut Proxy,
     params: lsp::CodeLensParams,
@@ -161,5 +253,99 @@ pub fn proxy_sync_doc_by_code_lens_request(
         }
     }
     try_ensure_bundle!(this, uri, params, code_lens);
-    Box::pin(async move { Ok(Some(vec![])) }) // TODO:
+
+    let lenses = match state.get_doc(uri) {
+        Ok(doc) => include_code_lenses(&state, &doc, uri),
+        Err(err) => return Box::pin(async move { Err(Error::internal(err)) }),
+    };
+
+    Box::pin(async move { Ok(Some(lenses)) })
+}
+
+/// "included by N files" and "includes M files" lenses at the top of script
+fn include_code_lenses(state: &State, doc: &Document, uri: &lsp::Url) -> Vec<lsp::CodeLens> {
+    let graph = state.get_include_graph();
+    let top = lsp::Range::default();
+
+    // several include sites of one file are listed but counted once
+    let includers = graph.get_includers(&doc.path);
+    let includers_count = includers
+        .iter()
+        .map(|(path, _)| path)
+        .collect::<HashSet<_>>()
+        .len();
+    let includers = (includers.into_iter())
+        .filter_map(|(path, range)| Some(lsp::Location::new(path_uri(state, &path)?, range)))
+        .collect::<Vec<_>>();
+
+    let includes = graph.get_transitive_includes(&doc.path);
+    let includes = (includes.into_iter())
+        .filter(|path| path.is_file())
+        .filter_map(|path| Some(lsp::Location::new(path_uri(state, &path)?, top)))
+        .collect::<Vec<_>>();
+
+    let lens = |title: String, locations: Vec<lsp::Location>| {
+        let arguments = vec![json!(uri), json!(top.start), json!(locations)];
+        let command = lsp::Command::new(title, SHOW_REFERENCES_COMMAND.into(), Some(arguments));
+        lsp::CodeLens {
+            range: top,
+            command: Some(command),
+            data: None,
+        }
+    };
+
+    vec![
+        lens(files_count("included by", includers_count), includers),
+        lens(files_count("includes", includes.len()), includes),
+    ]
+}
+
+fn path_uri(state: &State, path: &std::path::Path) -> Option<lsp::Url> {
+    state.path_to_uri(path).ok().map(|uri| (*uri).clone())
+}
+
+fn files_count(prefix: &str, count: usize) -> String {
+    match count {
+        1 => format!("{prefix} 1 file"),
+        _ => format!("{prefix} {count} files"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::state::testing::TestProject;
+
+    fn lens_title_and_locations(lens: &lsp::CodeLens) -> (&str, usize) {
+        let command = lens.command.as_ref().unwrap();
+        let locations = command.arguments.as_ref().unwrap()[2].as_array().unwrap();
+        (command.title.as_str(), locations.len())
+    }
+
+    #[test]
+    fn included_by_lens_counts_includer_files() {
+        let files = [
+            ("a.js", "#include <lib.js>\n#include <lib.js>\n"),
+            ("b.js", "#include <lib.js>\n"),
+            ("lib.js", ""),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        for script in ["a.js", "b.js"] {
+            project.state.get_doc(&project.uri(script)).unwrap();
+        }
+
+        let uri = project.uri("lib.js");
+        let doc = project.state.get_doc(&uri).unwrap();
+        let lenses = include_code_lenses(&project.state, &doc, &uri);
+        assert_eq!(
+            lens_title_and_locations(&lenses[0]),
+            ("included by 2 files", 3)
+        );
+        assert_eq!(
+            lens_title_and_locations(&lenses[1]),
+            ("includes 0 files", 0)
+        );
+    }
 }
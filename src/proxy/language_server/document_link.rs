@@ -0,0 +1,52 @@
+use async_lsp::lsp_types as lsp;
+use async_lsp::lsp_types::request as R;
+
+use crate::parser::Token;
+use crate::proxy::{Error, Proxy, ResFut};
+
+/// Resolved natively by proxy: tsserver doesn't know about include statements
+pub fn proxy_document_link(
+    this: &mut Proxy,
+    params: lsp::DocumentLinkParams,
+) -> ResFut<R::DocumentLinkRequest> {
+    let state = this.state.clone();
+    let doc = match state.get_doc(&params.text_document.uri) {
+        Ok(doc) => doc,
+        Err(err) => return Box::pin(async move { Err(Error::internal(err)) }),
+    };
+
+    let mut links = vec![];
+
+    for t in doc.parse.compressed_tokens.iter() {
+        let Token::IncludePath(sl) = t else {
+            continue;
+        };
+
+        let dep_paths = state.resolve_include_paths(&doc.path, sl.lit);
+        let (line, col) = (sl.line_col.line, sl.line_col.col + 1);
+        let range = lsp::Range::new(
+            lsp::Position::new(line, col),
+            lsp::Position::new(line, col + sl.lit.chars().count() as u32),
+        );
+
+        // link of glob include targets the first matched file
+        let target = dep_paths.iter().find(|p| p.is_file());
+        let target = target.and_then(|p| state.path_to_uri(p).ok().map(|uri| (*uri).clone()));
+        let paths = dep_paths.iter().map(|p| p.display().to_string());
+        let paths = paths.collect::<Vec<_>>().join("\n");
+
+        let tooltip = match target.is_some() {
+            true => paths,
+            false => format!("{paths} (unresolved)"),
+        };
+
+        links.push(lsp::DocumentLink {
+            range,
+            target,
+            tooltip: Some(tooltip),
+            data: None,
+        });
+    }
+
+    Box::pin(async move { Ok(Some(links)) })
+}
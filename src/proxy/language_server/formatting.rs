@@ -4,8 +4,9 @@ use async_lsp::lsp_types::request as R;
 use async_lsp::{LanguageServer, lsp_types as lsp};
 
 use crate::builder::Build;
-use crate::parser::Token;
+use crate::parser::{Token, conditional_blocks};
 use crate::proxy::{Error, Proxy, ResFut, forward_build_range};
+use crate::state::State;
 use crate::try_ensure_transpile;
 use crate::types::Document;
 
@@ -17,6 +18,7 @@ pub fn proxy_formatting(
     let mut s = this.server();
     let transpile = try_ensure_transpile!(this, &params.text_document.uri, params, formatting);
     let doc = this.state.get_doc(&params.text_document.uri).unwrap();
+    let sql_edits = sql_edits(&doc, &this.state, &params.options);
 
     params.text_document.uri = transpile.uri.clone();
 
@@ -25,13 +27,44 @@ pub fn proxy_formatting(
     Box::pin(async move {
         let fm = |e| forward(e, &transpile, &doc);
         match req.await.map_err(Error::internal) {
-            Ok(Some(e)) => Ok(Some(e.into_iter().filter_map(fm).collect())),
-            Ok(None) => Ok(None),
+            Ok(Some(e)) => Ok(Some(
+                e.into_iter().filter_map(fm).chain(sql_edits).collect(),
+            )),
+            Ok(None) if sql_edits.is_empty() => Ok(None),
+            Ok(None) => Ok(Some(sql_edits)),
             Err(err) => Err(err),
         }
     })
 }
 
+/// replaces content of active `#sql` regions by formatted statements
+fn sql_edits(
+    doc: &Document,
+    state: &State,
+    options: &lsp::FormattingOptions,
+) -> Vec<lsp::TextEdit> {
+    let indent = match options.insert_spaces {
+        true => sqlformat::Indent::Spaces(options.tab_size.min(u8::MAX as u32) as u8),
+        false => sqlformat::Indent::Tabs,
+    };
+    let options = sqlformat::FormatOptions {
+        indent,
+        ..Default::default()
+    };
+    let conditionals = state.get_conditionals(doc);
+    let regions = doc.sql_regions().into_iter();
+    let regions = regions.filter(|r| !conditionals.is_inactive(r.open_idx));
+
+    regions
+        .filter_map(|r| {
+            let start = lsp::Position::new(r.lines.start, 0);
+            let end = lsp::Position::new(r.lines.end, 0);
+            let new_text = r.format(options)?;
+            Some(lsp::TextEdit::new(lsp::Range::new(start, end), new_text))
+        })
+        .collect()
+}
+
 pub fn proxy_range_formatting(
     this: &mut Proxy,
     mut params: lsp::DocumentRangeFormattingParams,
@@ -79,6 +112,13 @@ fn forward(
             let t_range = lsp::Range::new(start, end);
             t_range.start <= edit.range.end && edit.range.start <= t_range.end
         }
+        Token::Directive(r) => {
+            let start = lsp::Position::new(r.line_col.line, r.line_col.col);
+            let end =
+                lsp::Position::new(start.line, start.character + r.text.chars().count() as u32);
+            let t_range = lsp::Range::new(start, end);
+            t_range.start <= edit.range.end && edit.range.start <= t_range.end
+        }
         _ => false,
     };
 
@@ -86,5 +126,12 @@ fn forward(
         return None;
     }
 
+    // directives and inactive branches are emitted as whitespace
+    let blocks = conditional_blocks(&doc.parse.compressed_tokens);
+    let (start, end) = (edit.range.start.line, edit.range.end.line);
+    if blocks.iter().any(|b| start < b.end && b.start <= end) {
+        return None;
+    }
+
     edit.into()
 }
@@ -9,7 +9,7 @@ use crate::proxy::language_server::{DefRes, definition_params};
 use crate::proxy::{Canonicalize, DECL_FILE_EXT, Proxy, ResFut, ResReqProxy};
 use crate::proxy::{Error, forward_build_range};
 
-use crate::state::State;
+use crate::state::{State, is_glob};
 use crate::types::{SCRIPT_IDENTIFIER_PREFIX, Source};
 use crate::{try_ensure_bundle, try_forward_text_document_position_params};
 
@@ -26,15 +26,28 @@ pub fn proxy_hover_with_decl_info(
     // TODO: send cancel req on timeout
     let decl_req = this.definition(definition_params(uri.clone(), pos.to_owned()));
     let state = this.state.clone();
-    let req_source = state.get_doc(uri).unwrap().source.clone();
+    let req_doc = state.get_doc(uri).unwrap();
+    let req_source = req_doc.source.clone();
     let req_uri = uri.clone();
+    let include_info = include_path_info(&state, uri, pos);
+    let interpolation = req_doc.get_interpolation(pos).map(|(range, _)| range);
+    let interpolation_target = req_doc.interpolation_target(pos);
 
     Box::pin(async move {
         let doc_pos = &mut params.text_document_position_params;
+        if let Some(target) = interpolation_target {
+            doc_pos.position = target;
+        }
         try_forward_text_document_position_params!(state, bundle, doc_pos);
 
         let Some(hover) = service.hover(params).await.map_err(Error::internal)? else {
-            return Ok(None);
+            return Ok(include_info.map(|msg| lsp::Hover {
+                contents: lsp::HoverContents::Markup(lsp::MarkupContent {
+                    kind: lsp::MarkupKind::Markdown,
+                    value: msg,
+                }),
+                range: None,
+            }));
         };
 
         let (stripped, hover) = strip_module_hash(hover);
@@ -46,6 +59,14 @@ pub fn proxy_hover_with_decl_info(
             hover.range = None
         }
 
+        if interpolation.is_some() {
+            hover.range = interpolation;
+        }
+
+        if let Some(msg) = include_info {
+            return Ok(Some(prepend_hover(hover, &msg)));
+        }
+
         let decl: ResReqProxy<R::GotoDefinition> = timeout(Duration::from_millis(200), decl_req)
             .await
             .unwrap_or(Ok(None));
@@ -93,7 +114,7 @@ pub fn proxy_hover_with_decl_info(
                                 .unwrap_or(s.as_str().to_string())
                         };
 
-                        let stack = bundle.sources_stack.get(&source).unwrap();
+                        let stack = state.get_source_stack(&bundle, &source).unwrap();
                         let stack_last_idx = stack.len() - 1;
 
                         let stack = stack
@@ -117,6 +138,40 @@ pub fn proxy_hover_with_decl_info(
     })
 }
 
+/// resolved path of hovered include path with root which it is resolved by
+fn include_path_info(state: &State, uri: &Uri, pos: &lsp::Position) -> Option<String> {
+    let doc = state.get_doc(uri).ok()?;
+    let lit = doc.get_include_path(pos)?.lit;
+    let project = state.get_project();
+    let md_link = |path: &std::path::Path| {
+        let source = path.strip_prefix(project).unwrap_or(path);
+        let source = source.to_string_lossy().replace('\\', "/");
+        match Uri::from_file_path(path).ok().filter(|_| path.is_file()) {
+            Some(uri) => Ok(format!("[{source}]({uri})")),
+            None => Err(source),
+        }
+    };
+
+    if is_glob(lit) {
+        let paths = state.resolve_include_paths(&doc.path, lit);
+        let links = paths
+            .iter()
+            .filter_map(|p| md_link(p).ok())
+            .collect::<Vec<_>>();
+        return Some(match links.is_empty() {
+            true => "No scripts matched by glob".to_string(),
+            false => format!("Matched by glob -> {}", links.join(", ")),
+        });
+    }
+
+    let (path, root) = state.resolve_include_path(&doc.path, lit);
+    let info = match md_link(&path) {
+        Ok(link) => format!("Resolved by {root} -> {link}"),
+        Err(source) => format!("Unresolved by {root} -> {source}"),
+    };
+    Some(info)
+}
+
 fn prepend_hover(mut hover: lsp::Hover, msg: &str) -> lsp::Hover {
     type H = lsp::HoverContents;
     type S = lsp::MarkedString;
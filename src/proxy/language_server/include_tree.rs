@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_lsp::lsp_types::{self as lsp, Url as Uri};
+use serde::{Deserialize, Serialize};
+
+use crate::builder::{Build, BuildOptionsBuilder};
+use crate::proxy::{Error, Proxy, ResFut};
+use crate::state::State;
+use crate::types::Source;
+
+/// Custom request: transitive include tree of document (with implicit default document)
+pub enum IncludeTreeRequest {}
+
+impl lsp::request::Request for IncludeTreeRequest {
+    type Params = IncludeTreeParams;
+    type Result = Option<IncludeTreeNode>;
+    const METHOD: &'static str = "glscript/includeTree";
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncludeTreeParams {
+    pub text_document: lsp::TextDocumentIdentifier,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IncludeTreeNode {
+    /// resolved [`Uri`] of included document
+    pub uri: Uri,
+    /// project relative path of included document
+    pub source: String,
+    /// range of include path (with brackets) in parent document, none for root and implicit node
+    pub range: Option<lsp::Range>,
+    /// included by default for all scripts
+    pub implicit: bool,
+    pub children: Vec<IncludeTreeNode>,
+}
+
+/// Resolved natively by proxy from [`Build::sources_stack`] of document bundle (and shared prelude)
+pub fn proxy_include_tree(
+    this: &mut Proxy,
+    params: IncludeTreeParams,
+) -> ResFut<IncludeTreeRequest> {
+    let uri = &params.text_document.uri;
+    let state = this.state.clone();
+
+    let bundle = match state.get_bundle(uri) {
+        Some(bundle) => {
+            state.commit_changes(uri, &mut this.server());
+            bundle
+        }
+        None => match Build::create(BuildOptionsBuilder::init(uri, &state)) {
+            Ok(bundle) => Arc::new(bundle),
+            Err(err) => return Box::pin(async move { Err(Error::request_failed(err)) }),
+        },
+    };
+
+    let tree = match state.get_doc(uri) {
+        Ok(doc) => include_tree(&state, &bundle, &doc.source),
+        Err(err) => return Box::pin(async move { Err(Error::internal(err)) }),
+    };
+
+    Box::pin(async move { Ok(tree) })
+}
+
+fn include_tree(state: &State, bundle: &Build, root: &Source) -> Option<IncludeTreeNode> {
+    let default_source = state.get_doc(&state.get_default_doc()).ok();
+    let default_source = default_source.map(|d| (*d.source).clone());
+
+    // parent source -> (include site, child source)
+    let sources_stack = state.get_sources_stack(bundle);
+    let mut children: HashMap<&Source, Vec<(Option<lsp::Range>, &Source)>> = HashMap::new();
+    for (source, stack) in sources_stack.iter() {
+        let Some((_, lc, len)) = stack.last() else {
+            continue;
+        };
+
+        let parent = match stack.len() {
+            1 => root,
+            n => &stack[n - 2].0,
+        };
+
+        let range = match Some(source) == default_source.as_ref() {
+            true => None,
+            false => {
+                let start = lsp::Position::new(lc.line, lc.col);
+                let end = lsp::Position::new(lc.line, lc.col + *len as u32 + 2);
+                Some(lsp::Range::new(start, end))
+            }
+        };
+
+        children.entry(parent).or_default().push((range, source));
+    }
+
+    // implicit default document first, then in order of include statements
+    children.values_mut().for_each(|c| {
+        c.sort_unstable_by_key(|(r, _)| r.map(|r| (r.start.line, r.start.character)))
+    });
+
+    let node = |source: &Source, range: Option<lsp::Range>| {
+        let path = state.get_project().join(source.as_str());
+        let uri = (*state.path_to_uri(&path).ok()?).clone();
+        let implicit = range.is_none() && Some(source) == default_source.as_ref();
+        let source = source.to_string();
+        Some(IncludeTreeNode {
+            uri,
+            source,
+            range,
+            implicit,
+            children: vec![],
+        })
+    };
+
+    fn fill(
+        node: &mut IncludeTreeNode,
+        source: &Source,
+        children: &HashMap<&Source, Vec<(Option<lsp::Range>, &Source)>>,
+        create: &impl Fn(&Source, Option<lsp::Range>) -> Option<IncludeTreeNode>,
+    ) {
+        for (range, child) in children.get(source).into_iter().flatten() {
+            if let Some(mut child_node) = create(child, *range) {
+                fill(&mut child_node, child, children, create);
+                node.children.push(child_node);
+            }
+        }
+    }
+
+    let mut tree = node(root, None)?;
+    fill(&mut tree, root, &children, &node);
+    Some(tree)
+}
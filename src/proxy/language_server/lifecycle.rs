@@ -4,15 +4,17 @@ use tokio::time::timeout;
 use async_lsp::lsp_types::{Url as Uri, notification as N, request as R};
 use async_lsp::{LanguageServer, lsp_types as lsp};
 
+use crate::proxy::JSCONFIG_FILENAME;
+use crate::proxy::language_server::{
+    BUNDLE_FILE_CONTENTS, INTERPOLATION_TRIGGER_CHARACTER, did_open,
+};
 use crate::proxy::{DEFAULT_TIMEOUT_MS, Error, NotifyResult, PROXY_WORKSPACE, Proxy, ResFut};
 
 pub fn initialize(this: &mut Proxy, mut params: lsp::InitializeParams) -> ResFut<R::Initialize> {
-    const JSCONFIG: &str = "jsconfig.json";
-
     if let Some([root_ws, ..]) = params.workspace_folders.as_deref_mut() {
         let ws_dir = &root_ws.uri.to_file_path().unwrap();
         let proxy_ws_dir = &mut ws_dir.clone().join(PROXY_WORKSPACE);
-        let jsconfig_content = std::fs::read(ws_dir.join(JSCONFIG))
+        let jsconfig_content = std::fs::read(ws_dir.join(JSCONFIG_FILENAME))
             .map(|b| String::from_utf8_lossy(&b).into_owned())
             .unwrap_or("{}".to_string())
             .replace("./node_modules/@types", "../../node_modules/@types");
@@ -24,9 +26,20 @@ pub fn initialize(this: &mut Proxy, mut params: lsp::InitializeParams) -> ResFut
             .map(|t| t.unwrap_or_default());
 
         std::fs::create_dir_all(&proxy_ws_dir).unwrap();
-        std::fs::write(proxy_ws_dir.join(JSCONFIG), jsconfig_content).unwrap();
+        std::fs::write(proxy_ws_dir.join(JSCONFIG_FILENAME), jsconfig_content).unwrap();
 
         this.state.initialize_project(&root_ws.uri, token_types);
+        (this.state).initialize_include_roots(params.initialization_options.as_ref());
+        (this.state).initialize_defines(params.initialization_options.as_ref());
+        (this.state).initialize_region_kinds(params.initialization_options.as_ref());
+
+        if std::env::args().any(|a| a.trim().eq("--build-cache")) {
+            this.state.enable_disk_cache();
+        }
+
+        if std::env::args().any(|a| a.trim().eq("--shared-prelude")) {
+            this.state.enable_shared_prelude();
+        }
 
         let default_doc = this.state.get_default_doc();
         let _ = std::fs::File::create_new(default_doc.to_file_path().unwrap());
@@ -52,7 +65,23 @@ pub fn initialize(this: &mut Proxy, mut params: lsp::InitializeParams) -> ResFut
 
         let res = match res.map_err(Error::internal) {
             Err(_) => std::process::exit(1),
-            Ok(r) => Ok(r),
+            Ok(mut r) => {
+                // resolved by proxy natively
+                r.capabilities.document_link_provider = Some(lsp::DocumentLinkOptions {
+                    resolve_provider: Some(false),
+                    work_done_progress_options: Default::default(),
+                });
+                if let Some(completion) = r.capabilities.completion_provider.as_mut() {
+                    let triggers = completion.trigger_characters.get_or_insert_default();
+                    triggers.push(INTERPOLATION_TRIGGER_CHARACTER.into());
+                }
+                r.capabilities
+                    .code_lens_provider
+                    .get_or_insert(lsp::CodeLensOptions {
+                        resolve_provider: Some(false),
+                    });
+                Ok(r)
+            }
         };
 
         let need_restart = match self_update() {
@@ -83,6 +112,12 @@ pub fn initialize(this: &mut Proxy, mut params: lsp::InitializeParams) -> ResFut
 
 pub fn initialized(this: &mut Proxy, params: lsp::InitializedParams) -> NotifyResult {
     let _ = this.server().initialized(params);
+
+    // opened once for all bundles which reference it
+    if let Some(prelude) = this.state.get_shared_prelude() {
+        let _ = std::fs::write(prelude.uri.to_file_path().unwrap(), BUNDLE_FILE_CONTENTS);
+        let _ = did_open(&mut this.server(), &prelude.uri, &prelude.content, None);
+    }
     std::ops::ControlFlow::Continue(())
 }
 
@@ -43,6 +43,7 @@ pub fn proxy_workspace_references(
 
     let req_bundle = try_ensure_bundle!(this, uri, p, references);
     let definition_request = this.definition(definition_params(uri.clone(), pos.to_owned()));
+    let interpolation_target = st.get_doc(uri).unwrap().interpolation_target(pos);
 
     Box::pin(async move {
         let def_loc = match definition_request.await? {
@@ -52,6 +53,9 @@ pub fn proxy_workspace_references(
 
         if def_loc.target_uri.as_str().ends_with(DECL_FILE_EXT) {
             let doc_pos = &mut p.text_document_position;
+            if let Some(target) = interpolation_target {
+                doc_pos.position = target;
+            }
             try_forward_text_document_position_params!(st, req_bundle, doc_pos);
             return fetch_with_build_params(&mut s, &st, &root, p, req_bundle, None).await;
         }
@@ -250,8 +254,14 @@ async fn fetch_with_build_params(
         .map(|mut r| {
             r.iter_mut().for_each(|l| {
                 let req_uri = temp.clone().unwrap_or_else(|| build.uri.try_canonicalize());
-                if req_uri == l.uri.try_canonicalize()
-                    && let Ok(source) = forward_build_range(&mut l.range, &build)
+                let l_build = match req_uri == l.uri.try_canonicalize() {
+                    true => Some(build.clone()),
+                    // symbols of shared prelude are referenced by all opened bundles
+                    false if state.is_shared_prelude() => state.get_bundle_by_emit_uri(&l.uri),
+                    false => None,
+                };
+                if let Some(l_build) = l_build
+                    && let Ok(source) = forward_build_range(&mut l.range, &l_build)
                 {
                     let uri = state.path_to_uri(&project.join(source.as_str())).unwrap();
                     let uri = (*uri).clone();
@@ -275,9 +285,6 @@ fn file_contains_text<P: AsRef<Path>>(filename: P, search_term: &str) -> anyhow:
 }
 
 fn find_module_references(this: &Proxy, p: &lsp::ReferenceParams) -> ResFut<R::References> {
-    use ignore::Walk;
-    use rayon::prelude::*;
-
     let uri = &p.text_document_position.text_document.uri;
     let pos = &p.text_document_position.position;
 
@@ -293,48 +300,21 @@ fn find_module_references(this: &Proxy, p: &lsp::ReferenceParams) -> ResFut<R::R
         return Box::pin(async move { Ok(None) });
     };
 
-    let mut raw_entries = vec![];
-    for entry in Walk::new(root).flatten() {
-        if entry.file_type().is_some_and(|ft| ft.is_file()) {
-            raw_entries.push(entry.path().to_owned());
-        }
-    }
-
-    let module_refs: Vec<_> = raw_entries
-        .par_iter()
-        .filter_map(|p| {
-            let uri = st.path_to_uri(p.as_path()).ok()?;
-            let uri = (*uri).clone();
-            p.extension()?.eq(&JS_FILE_EXT[1..]).then_some(0)?;
-
-            let find_module_refs = |t: Arc<Build>| {
-                t.sources_stack.iter().find_map(|deps| {
-                    deps.0.eq(&req_source).then(|| {
-                        deps.1
-                            .iter()
-                            .map(|(_, lc, len)| {
-                                let start = lsp::Position::new(lc.line, lc.col);
-                                let end = lsp::Position::new(lc.line, lc.col + *len as u32 + 2);
-                                let range = lsp::Range::new(start, end);
-                                lsp::Location::new(uri.clone(), range)
-                            })
-                            .collect::<Vec<_>>()
-                    })
-                })
-            };
+    let req_path = root.join(req_source.as_str());
+    let Some(req_path) = st
+        .path_to_uri(&req_path)
+        .and_then(|u| st.uri_to_path(&u))
+        .ok()
+    else {
+        return Box::pin(async move { Ok(None) });
+    };
 
-            if let Some(t) = st.get_transpile(&uri) {
-                find_module_refs(t)
-            } else {
-                let t = st.set_transpile(&uri).ok()?;
-                let refs = find_module_refs(t.build);
-                st.remove_transpile(&uri);
-                refs
-            }
+    let module_refs = (st.get_include_graph().get_includers(&req_path).into_iter())
+        .filter_map(|(path, range)| {
+            let uri = st.path_to_uri(&path).ok()?;
+            Some(lsp::Location::new((*uri).clone(), range))
         })
-        .collect();
-
-    let module_refs = module_refs.into_iter().flatten().collect::<Vec<_>>();
+        .collect::<Vec<_>>();
 
     Box::pin(async move { Ok(Some(module_refs)) })
 }
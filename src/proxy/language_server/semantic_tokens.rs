@@ -4,11 +4,13 @@ use derive_more::Constructor;
 use rayon::prelude::*;
 
 use crate::builder::Build;
+use crate::parser::{SqlHighlight, SqlRegion, SqlTokenKind, Token};
 use crate::proxy::{Error, Proxy, ResFut, forward_build_range};
 use crate::state::State;
 use crate::try_ensure_transpile;
+use crate::types::Document;
 
-// TODO: add %param str injection, mono-highlight regions (with provided option)
+// TODO: add %param str injection
 /// wiki:
 /// - <https://pygls.readthedocs.io/en/latest/protocol/howto/interpret-semantic-tokens.html>
 /// - [`lsp::SemanticTokens`] on prop `data`
@@ -91,18 +93,114 @@ async fn forward(
 
 fn extra_tokens(transpile: &Build, st: &State) -> Option<Vec<AbsoluteSemanticToken>> {
     let token_types = st.get_token_types_capabilities()?;
-    let id = token_types
-        .iter()
-        .enumerate()
-        .find(|(_, t)| **t == lsp::SemanticTokenType::TYPE)
-        .map(|e| e.0 as u32)?;
-    st.get_doc_by_emit_uri(&transpile.uri)?
+    let type_id = |tt: &lsp::SemanticTokenType| {
+        let id = token_types.iter().position(|t| t == tt);
+        id.map(|id| id as u32)
+    };
+    let id = type_id(&lsp::SemanticTokenType::TYPE)?;
+    let doc = st.get_doc_by_emit_uri(&transpile.uri)?;
+    let sql_regions = doc.sql_regions();
+    let in_sql = |line: u32| sql_regions.iter().any(|r| r.lines.contains(&line));
+    let mut tokens = doc
         .parse
         .str_interpolations
         .iter()
+        .filter(|t| !in_sql(t.line))
         .map(|t| AbsoluteSemanticToken::new((lsp::Position::new(t.line, t.col), t.col + 2), id, 0))
-        .collect::<Vec<AbsoluteSemanticToken>>()
-        .into()
+        .collect::<Vec<AbsoluteSemanticToken>>();
+
+    tokens.extend(region_tokens(&doc, st, type_id));
+    tokens.extend(sql_tokens(&sql_regions, type_id));
+    tokens.into()
+}
+
+/// keywords, literals, comments and `%ident` parameters of `#sql` regions
+fn sql_tokens(
+    sql_regions: &[SqlRegion],
+    type_id: impl Fn(&lsp::SemanticTokenType) -> Option<u32>,
+) -> Vec<AbsoluteSemanticToken> {
+    type TT = lsp::SemanticTokenType;
+    let highlights = sql_regions.iter().flat_map(|r| r.highlights());
+    let to_token = |h: SqlHighlight| {
+        let token_type = match h.kind {
+            SqlTokenKind::Keyword => TT::KEYWORD,
+            SqlTokenKind::String => TT::STRING,
+            SqlTokenKind::Number => TT::NUMBER,
+            SqlTokenKind::Comment => TT::COMMENT,
+            SqlTokenKind::Parameter => TT::PARAMETER,
+        };
+        let start = lsp::Position::new(h.line_col.line, h.line_col.col);
+        let range = (start, start.character + h.len);
+        Some(AbsoluteSemanticToken::new(range, type_id(&token_type)?, 0))
+    };
+
+    highlights.filter_map(to_token).collect()
+}
+
+/// content lines of regions highlighted by semantic token type of region kind (around
+/// string interpolations), except of `#sql` regions
+fn region_tokens(
+    doc: &Document,
+    st: &State,
+    type_id: impl Fn(&lsp::SemanticTokenType) -> Option<u32>,
+) -> Vec<AbsoluteSemanticToken> {
+    let mut tokens = vec![];
+    let mut open_line = None;
+
+    for t in doc.parse.compressed_tokens.iter() {
+        let close_line = match t {
+            Token::RegionOpen(s) => {
+                open_line = Some(s.line_col.line);
+                continue;
+            }
+            Token::RegionClose(s) => s.line_col.line,
+            _ => continue,
+        };
+
+        let Some(open_line) = open_line.take() else {
+            continue;
+        };
+        // highlighted by sql tokens
+        if doc.get_region_kind(open_line) == Some("sql") {
+            continue;
+        }
+
+        let region = st.get_region_kind(doc, open_line);
+        let Some(id) = region
+            .and_then(|r| r.token_type.as_ref())
+            .and_then(&type_id)
+        else {
+            continue;
+        };
+
+        for line in open_line + 1..close_line {
+            let Some(text) = doc.buffer.get_line(line as usize) else {
+                continue;
+            };
+            let text = text.chars().filter(|c| !matches!(c, '\r' | '\n'));
+            let len = text.map(|c| c.len_utf16() as u32).sum::<u32>();
+            let mut start = 0;
+            let mut push = |from: u32, to: u32| {
+                if from < to {
+                    let range = (lsp::Position::new(line, from), to);
+                    tokens.push(AbsoluteSemanticToken::new(range, id, 0));
+                }
+            };
+
+            for i in doc
+                .parse
+                .str_interpolations
+                .iter()
+                .filter(|i| i.line == line)
+            {
+                push(start, i.col);
+                start = i.col + 2;
+            }
+            push(start, len);
+        }
+    }
+
+    tokens
 }
 
 fn enrich_tokens(
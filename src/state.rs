@@ -1,19 +1,32 @@
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
 
 use async_lsp::lsp_types as lsp;
 use async_lsp::lsp_types::Url as Uri;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 
-use crate::types::BuildWithVersion;
-use crate::types::Document;
+use crate::builder::{ContentSegment, SourceMapSegment};
+use crate::state::declarations::DeclarationIndex;
+use crate::state::include_graph::IncludeGraph;
+use crate::types::{BuildWithVersion, Document, IncludeRoots, RegionKind, SourceHash};
 
 mod build;
 mod caches;
+mod conditionals;
 mod configuration;
+mod declarations;
+mod disk_cache;
 mod document;
+mod include_graph;
+mod includes;
 mod lazy_build_changes;
 mod progress;
+mod shared_prelude;
+#[cfg(test)]
+pub mod testing;
+
+pub use includes::{is_glob, relative_literal};
 
 type UnforwardedDocChanges = DashMap<PathBuf, Vec<(lsp::DidChangeTextDocumentParams, bool)>>; // Vec<(_, dependency_changed)>
 pub type UnforwardedBuildChanges = DashMap<PathBuf, Vec<lsp::DidChangeTextDocumentParams>>;
@@ -28,18 +41,37 @@ pub struct State {
 
     project: Arc<OnceLock<PathBuf>>,
     token_types_capabilities: Arc<OnceLock<Vec<lsp::SemanticTokenType>>>,
+    include_roots: Arc<OnceLock<IncludeRoots>>,
+    region_kinds: Arc<OnceLock<HashMap<String, RegionKind>>>,
     tsserver_initialized: Arc<OnceLock<bool>>,
+    disk_cache: Arc<OnceLock<PathBuf>>,
+    shared_prelude: Arc<OnceLock<bool>>,
+    configured_defines: Arc<OnceLock<HashSet<String>>>,
+    defines: Arc<RwLock<Arc<HashSet<String>>>>,
 
     documents: DashMap<PathBuf, Document>,
     current_doc: Arc<Mutex<Option<Uri>>>,
     doc_to_bundle: BuildStorage,
     doc_to_transpile: BuildStorage,
+    include_graph: Arc<RwLock<IncludeGraph>>,
+    outdated_includes: DashSet<PathBuf>,
 
     unforwarded_doc_changes: UnforwardedDocChanges,
     uncommitted_bundle_changes: UnforwardedBuildChanges,
     uncommitted_transpile_changes: UnforwardedBuildChanges,
 
     path_resolver_cache: DashMap<(PathBuf, String), Arc<PathBuf>>,
+    include_globs_cache: DashMap<(PathBuf, String), Arc<Vec<PathBuf>>>,
+    project_scripts_cache: Arc<RwLock<Option<Arc<Vec<PathBuf>>>>>,
     uri_to_canonicalized_path: DashMap<Uri, Arc<PathBuf>>,
     path_to_canonicalized_uri: DashMap<PathBuf, Arc<Uri>>,
+
+    content_segments: DashMap<SourceHash, Arc<ContentSegment>>,
+    sourcemap_segments: DashMap<SourceHash, Arc<SourceMapSegment>>,
+    /// last published diagnostics of documents: others (forwarded from TSServer and
+    /// reported by proxy) and syntax ones
+    published_diagnostics: DashMap<PathBuf, (Vec<lsp::Diagnostic>, Vec<lsp::Diagnostic>)>,
+    /// top-level declarations of indexed project scripts
+    declarations: Arc<RwLock<DeclarationIndex>>,
+    outdated_declarations: DashSet<PathBuf>,
 }
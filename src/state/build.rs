@@ -13,13 +13,13 @@ impl State {
     #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
     pub fn set_bundle(&self, source_uri: &Uri) -> anyhow::Result<BuildWithVersion> {
         let opt = BuildOptionsBuilder::init(source_uri, self);
-        self.build(opt, &self.doc_to_bundle)
+        self.build(opt, &self.doc_to_bundle, true)
     }
 
     #[cfg_attr(feature = "profiling", tracing::instrument(skip_all))]
     pub fn set_transpile(&self, source_uri: &Uri) -> anyhow::Result<BuildWithVersion> {
         let opt = BuildOptionsBuilder::init(source_uri, self).transpile_mode();
-        self.build(opt, &self.doc_to_transpile)
+        self.build(opt, &self.doc_to_transpile, false)
     }
 
     pub fn set_bundle_with_tree_shaking(
@@ -28,7 +28,7 @@ impl State {
         pat: SourcePattern,
     ) -> anyhow::Result<BuildWithVersion> {
         let opt = BuildOptionsBuilder::init(source_uri, self).with_source_pattern(pat);
-        self.build(opt, &self.doc_to_bundle)
+        self.build(opt, &self.doc_to_bundle, false)
     }
 
     pub fn get_bundle(&self, source_uri: &Uri) -> Option<Arc<Build>> {
@@ -67,6 +67,11 @@ impl State {
         }
     }
 
+    /// returns SourcePath of all bundles
+    pub fn get_bundles(&self) -> Vec<PathBuf> {
+        self.doc_to_bundle.iter().map(|e| e.key().clone()).collect()
+    }
+
     /// returns SourcePath for canonicalize interface
     pub fn get_bundles_contains_source(&self, source: &Source) -> Vec<PathBuf> {
         self.doc_to_bundle
@@ -94,15 +99,26 @@ impl State {
 }
 
 impl State {
+    /// `disk_cache`: restore build from disk cache (and cache it) if there is no build yet
     fn build(
         &self,
         opt: BuildOptionsBuilder,
         s: &BuildStorage,
+        disk_cache: bool,
     ) -> anyhow::Result<BuildWithVersion> {
         let path = self.uri_to_path(opt.target())?;
         let path = &(*path).clone();
         let Some(mut cur_build) = s.get_mut(path) else {
-            let new_build = Build::create(opt)?;
+            let target = opt.target();
+            let new_build = match disk_cache.then(|| self.get_cached_bundle(target)) {
+                Some(Some(cached_build)) => cached_build,
+                Some(None) => {
+                    let new_build = Build::create(opt)?;
+                    self.set_cached_bundle(target, &new_build);
+                    new_build
+                }
+                None => Build::create(opt)?,
+            };
             let build_with_version = BuildWithVersion::new(new_build.into(), 1);
             s.insert(path.into(), build_with_version.clone());
             return Ok(build_with_version);
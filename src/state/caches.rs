@@ -1,10 +1,13 @@
-use std::path::{Component, Path, PathBuf};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
+use async_lsp::lsp_types as lsp;
 use async_lsp::lsp_types::Url as Uri;
 
+use crate::builder::{ContentSegment, SourceMapSegment};
 use crate::proxy::Canonicalize;
 use crate::state::State;
+use crate::types::{Document, SourceHash};
 
 impl State {
     /// returns canonicalized [`PathBuf`]
@@ -49,33 +52,186 @@ impl State {
             return resolved_path.clone();
         }
 
-        let is_relative = |path: &str| {
-            path.starts_with("./")
-                || path.starts_with(".\\")
-                || path.starts_with("../")
-                || path.starts_with("..\\")
-        };
+        let resolved_path: Arc<PathBuf> =
+            self.resolve_include_path(path_from, path_literal).0.into();
+
+        self.path_resolver_cache.insert(key, resolved_path.clone());
+        resolved_path
+    }
+
+    /// emitted content segment of document if it is up to date
+    pub fn get_content_segment(&self, doc: &Document) -> Option<Arc<ContentSegment>> {
+        let segment = self.content_segments.get(&doc.source_hash)?;
+        (segment.version == doc.version).then(|| segment.clone())
+    }
+
+    pub fn set_content_segment(&self, source_hash: SourceHash, segment: ContentSegment) {
+        self.content_segments.insert(source_hash, segment.into());
+    }
+
+    /// emitted sourcemap segment of document if it is up to date
+    pub fn get_sourcemap_segment(&self, doc: &Document) -> Option<Arc<SourceMapSegment>> {
+        let segment = self.sourcemap_segments.get(&doc.source_hash)?;
+        (segment.version == doc.version).then(|| segment.clone())
+    }
+
+    pub fn set_sourcemap_segment(&self, source_hash: SourceHash, segment: SourceMapSegment) {
+        self.sourcemap_segments.insert(source_hash, segment.into());
+    }
+
+    pub fn remove_emit_segments(&self, source_hash: SourceHash) {
+        self.content_segments.remove(&source_hash);
+        self.sourcemap_segments.remove(&source_hash);
+    }
+
+    pub fn clear_emit_segments(&self) {
+        self.content_segments.clear();
+        self.sourcemap_segments.clear();
+    }
+
+    /// last published other and syntax diagnostics of `doc`
+    pub fn get_published_diagnostics(
+        &self,
+        doc: &Document,
+    ) -> (Vec<lsp::Diagnostic>, Vec<lsp::Diagnostic>) {
+        let published = self.published_diagnostics.get(doc.path.as_path());
+        published.map(|p| p.value().clone()).unwrap_or_default()
+    }
+
+    pub fn set_published_diagnostics(
+        &self,
+        doc: &Document,
+        other: Vec<lsp::Diagnostic>,
+        syntax: Vec<lsp::Diagnostic>,
+    ) {
+        let path = doc.path.to_path_buf();
+        self.published_diagnostics.insert(path, (other, syntax));
+    }
+
+    pub fn remove_published_diagnostics(&self, doc: &Document) {
+        self.published_diagnostics.remove(doc.path.as_path());
+    }
 
-        #[allow(clippy::unit_arg)]
-        let normilize = |path: &Path| {
-            let mut buf = PathBuf::new();
-            for component in path.components() {
-                match component {
-                    Component::ParentDir => buf.pop().eq(&false).then(|| buf.push("..")),
-                    Component::CurDir => None,
-                    _ => buf.push(component.as_os_str()).into(),
-                };
-            }
-            buf
+    /// other diagnostics of changed document are moved by `change` until TSServer publishes
+    /// diagnostics of build again (ones overlapped by the change are dropped)
+    pub fn shift_published_diagnostics(
+        &self,
+        path: &Path,
+        change: &lsp::TextDocumentContentChangeEvent,
+    ) {
+        let Some(mut published) = self.published_diagnostics.get_mut(path) else {
+            return;
         };
 
-        let path = path_literal.replace("\\\\", "/").replace("\\", "/");
-        let resolved_path: Arc<PathBuf> = match is_relative(&path) {
-            true => normilize(&path_from.parent().unwrap().join(path)).into(),
-            false => normilize(&self.get_project().join(path)).into(),
+        let other = &mut published.value_mut().0;
+        match &change.range {
+            Some(edit) => other.retain_mut(|d| {
+                shift_range(d.range, edit, &change.text).is_some_and(|r| {
+                    d.range = r;
+                    true
+                })
+            }),
+            None => other.clear(),
+        }
+    }
+}
+
+/// `range` moved by replacement of `edit` range with `text` (`None` if they overlap)
+fn shift_range(range: lsp::Range, edit: &lsp::Range, text: &str) -> Option<lsp::Range> {
+    if range.end <= edit.start {
+        return Some(range);
+    }
+    if range.start < edit.end {
+        return None;
+    }
+
+    let lines = text.split('\n').collect::<Vec<_>>();
+    let last_len = lines.last().map_or(0, |l| l.chars().count()) as u32;
+    let new_end = match lines.len() {
+        1 => lsp::Position::new(edit.start.line, edit.start.character + last_len),
+        n => lsp::Position::new(edit.start.line + n as u32 - 1, last_len),
+    };
+
+    let shift = |pos: lsp::Position| match pos.line == edit.end.line {
+        true => lsp::Position::new(
+            new_end.line,
+            new_end.character + pos.character - edit.end.character,
+        ),
+        false => lsp::Position::new(pos.line + new_end.line - edit.end.line, pos.character),
+    };
+    Some(lsp::Range::new(shift(range.start), shift(range.end)))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use super::shift_range;
+    use crate::state::testing::TestProject;
+
+    fn range(sl: u32, sc: u32, el: u32, ec: u32) -> lsp::Range {
+        lsp::Range::new(lsp::Position::new(sl, sc), lsp::Position::new(el, ec))
+    }
+
+    #[test]
+    fn ranges_are_shifted_by_edit() {
+        let edit = range(1, 4, 1, 6);
+        // before the edit and after it on other lines
+        assert_eq!(
+            shift_range(range(0, 0, 1, 4), &edit, "ab\nc"),
+            Some(range(0, 0, 1, 4))
+        );
+        assert_eq!(
+            shift_range(range(2, 1, 2, 3), &edit, "ab\nc"),
+            Some(range(3, 1, 3, 3))
+        );
+        assert_eq!(
+            shift_range(range(2, 1, 2, 3), &edit, ""),
+            Some(range(2, 1, 2, 3))
+        );
+        // after the edit on its end line
+        assert_eq!(
+            shift_range(range(1, 6, 1, 8), &edit, "abc"),
+            Some(range(1, 7, 1, 9))
+        );
+        assert_eq!(
+            shift_range(range(1, 6, 2, 0), &edit, "ab\nc"),
+            Some(range(2, 1, 3, 0))
+        );
+        // overlapped by the edit
+        assert_eq!(shift_range(range(1, 5, 1, 8), &edit, "abc"), None);
+        assert_eq!(shift_range(range(0, 0, 1, 5), &edit, ""), None);
+    }
+
+    #[test]
+    fn published_diagnostics_are_shifted_by_changes() {
+        let project = TestProject::new(&[("main.js", "var a = b;\nvar c = d;\n")], json!({}));
+        let (st, uri) = (&project.state, project.uri("main.js"));
+        let doc = st.get_doc(&uri).unwrap();
+
+        let diagnostic = |r: lsp::Range| lsp::Diagnostic::new_simple(r, "cannot find name".into());
+        let other = vec![diagnostic(range(0, 8, 0, 9)), diagnostic(range(1, 8, 1, 9))];
+        let syntax = vec![diagnostic(range(0, 0, 0, 1))];
+        st.set_published_diagnostics(&doc, other, syntax.clone());
+
+        let change = |range, text: &str| lsp::TextDocumentContentChangeEvent {
+            range,
+            range_length: None,
+            text: text.into(),
         };
+        // line inserted before second line, first diagnostic is edited
+        let changes = [
+            change(Some(range(1, 0, 1, 0)), "var x;\n"),
+            change(Some(range(0, 8, 0, 9)), "e"),
+        ];
+        st.set_doc(&uri, &changes).unwrap();
 
-        self.path_resolver_cache.insert(key, resolved_path.clone());
-        resolved_path
+        let (other, published_syntax) = st.get_published_diagnostics(&doc);
+        assert_eq!(other, [diagnostic(range(2, 8, 2, 9))]);
+        assert_eq!(published_syntax, syntax);
+
+        st.set_doc(&uri, &[change(None, "var f;\n")]).unwrap();
+        assert!(st.get_published_diagnostics(&doc).0.is_empty());
     }
 }
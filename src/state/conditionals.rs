@@ -0,0 +1,51 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use crate::parser::Conditionals;
+use crate::state::State;
+use crate::types::Document;
+
+/// State of conditional compilation
+impl State {
+    /// names of `defines` initialization option
+    pub fn initialize_defines(&self, options: Option<&serde_json::Value>) {
+        let defines = options.and_then(|o| o.get("defines"));
+        let defines = defines.and_then(serde_json::Value::as_array).into_iter();
+        let defines = defines.flatten().filter_map(serde_json::Value::as_str);
+        let defines = defines.map(str::to_string).collect::<HashSet<_>>();
+
+        *self.defines.write().unwrap() = defines.clone().into();
+        let msg = "defines initialize once";
+        self.configured_defines.set(defines).expect(msg);
+    }
+
+    /// configured names and names of `#define` directives of default document
+    pub fn get_defines(&self) -> Arc<HashSet<String>> {
+        self.defines.read().unwrap().clone()
+    }
+
+    /// `#define` of default document applies to every document, so emitted segments and
+    /// include graph edges of all documents are outdated once defined names are changed
+    pub fn set_doc_defines(&self, doc: &Document) {
+        let default_doc = self.uri_to_path(&self.get_default_doc());
+        if !default_doc.is_ok_and(|p| p == doc.path) {
+            return;
+        }
+
+        let configured = self.configured_defines.get().cloned().unwrap_or_default();
+        let tokens = &doc.parse.compressed_tokens;
+        let defines = Conditionals::evaluate(tokens, &configured).defined;
+
+        let mut current = self.defines.write().unwrap();
+        if **current != defines {
+            *current = defines.into();
+            self.clear_emit_segments();
+            self.outdate_includes_by_defines();
+        }
+    }
+
+    /// branches of document evaluated by current defines
+    pub fn get_conditionals(&self, doc: &Document) -> Conditionals {
+        Conditionals::evaluate(&doc.parse.compressed_tokens, &self.get_defines())
+    }
+}
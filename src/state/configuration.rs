@@ -1,11 +1,14 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
 use async_lsp::lsp_types::Url as Uri;
 use async_lsp::{ClientSocket, lsp_types as lsp};
 
+use crate::parser::Token;
 use crate::proxy::{Canonicalize, DEFAULT_SCRIPT_FILENAME, PROXY_WORKSPACE};
-use crate::proxy::{DECL_FILE_EXT, JS_FILE_EXT};
+use crate::proxy::{DECL_FILE_EXT, JS_FILE_EXT, JSCONFIG_FILENAME};
 use crate::state::State;
+use crate::types::{Document, IncludeRoots, InterpolationReference, RegionKind};
 
 /// State of configuration
 impl State {
@@ -39,6 +42,169 @@ impl State {
         default_doc.unwrap_or(Uri::from_file_path(path).unwrap().canonicalize().unwrap())
     }
 
+    /// include search paths and aliases from initialization options (`includePaths` and
+    /// `includeAliases`) followed by `compilerOptions.paths` of project jsconfig
+    pub fn initialize_include_roots(&self, options: Option<&serde_json::Value>) {
+        use serde_json::Value;
+
+        let project = self.get_project();
+        let mut roots = IncludeRoots::default();
+
+        // directories must be inside of project: sources are relative to project root
+        let dir = |path: &str| {
+            let dir = dunce::canonicalize(project.join(path))
+                .ok()
+                .filter(|d| d.is_dir());
+            let relative = dir.as_ref().and_then(|d| d.strip_prefix(project).ok());
+            if relative.is_none() {
+                tracing::warn!("include root {path} is not a directory of project");
+            }
+            relative.map(|r| r.to_string_lossy().replace('\\', "/"))
+        };
+        let option = |key: &str| options.and_then(|o| o.get(key));
+
+        for path in option("includePaths")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            roots.search_paths.extend(path.as_str().and_then(dir));
+        }
+
+        for (prefix, targets) in option("includeAliases")
+            .and_then(Value::as_object)
+            .into_iter()
+            .flatten()
+        {
+            let targets = match targets {
+                Value::Array(targets) => targets.iter().collect(),
+                target => vec![target],
+            };
+            for target in targets
+                .into_iter()
+                .filter_map(Value::as_str)
+                .filter_map(dir)
+            {
+                roots.aliases.push((prefix.clone(), target));
+            }
+        }
+
+        let jsconfig = std::fs::read_to_string(project.join(JSCONFIG_FILENAME)).ok();
+        let jsconfig = jsconfig.and_then(|c| serde_json::from_str::<Value>(&c).ok());
+        let compiler_options = jsconfig.as_ref().and_then(|c| c.get("compilerOptions"));
+        let base_url = compiler_options
+            .and_then(|c| c.get("baseUrl"))
+            .and_then(Value::as_str);
+        let paths = compiler_options
+            .and_then(|c| c.get("paths"))
+            .and_then(Value::as_object);
+
+        // only wildcard patterns like `"@core/*": ["lib/core/*"]` are aliases
+        for (pattern, targets) in paths.into_iter().flatten() {
+            let Some(prefix) = pattern.strip_suffix('*').filter(|p| !p.is_empty()) else {
+                continue;
+            };
+            for target in targets
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+            {
+                let Some(target) = target.strip_suffix('*') else {
+                    continue;
+                };
+                let target = format!("{}/{target}", base_url.unwrap_or("."));
+                roots
+                    .aliases
+                    .extend(dir(&target).map(|d| (prefix.to_string(), d)));
+            }
+        }
+
+        let msg = "include roots initialize once";
+        self.include_roots.set(roots).expect(msg);
+    }
+
+    pub fn get_include_roots(&self) -> Option<&IncludeRoots> {
+        self.include_roots.get()
+    }
+
+    /// emit wrappers and semantic token types of region kinds (`regions` initialization option)
+    pub fn initialize_region_kinds(&self, options: Option<&serde_json::Value>) {
+        let regions = options.and_then(|o| o.get("regions")).cloned();
+        let regions = regions.map(serde_json::from_value::<HashMap<String, RegionKind>>);
+        let mut regions = match regions.transpose() {
+            Ok(regions) => regions.unwrap_or_default(),
+            Err(err) => {
+                tracing::warn!("invalid regions option: {err}");
+                HashMap::default()
+            }
+        };
+
+        // wrapper is emitted on lines of region statements
+        regions.retain(|kind, r| {
+            let is_inline = !(r.prefix.contains(['\r', '\n']) || r.suffix.contains(['\r', '\n']));
+            if !is_inline {
+                tracing::warn!("emit wrapper of region {kind} should not contain line breaks");
+            }
+            is_inline
+        });
+
+        let msg = "region kinds initialize once";
+        self.region_kinds.set(regions).expect(msg);
+    }
+
+    /// user-defined region `kind` is configured, so its standalone `#kind` and `#endkind`
+    /// lines are region statements
+    pub fn is_region_kind(&self, kind: &str) -> bool {
+        self.region_kinds
+            .get()
+            .is_some_and(|r| r.contains_key(kind))
+    }
+
+    /// configured kind of region opened or closed on `line` of `doc`
+    pub fn get_region_kind(&self, doc: &Document, line: u32) -> Option<&RegionKind> {
+        let kind = doc.get_region_kind(line)?;
+        self.region_kinds.get()?.get(kind)
+    }
+
+    /// referenced `%ident` interpolations of `doc`: ones of regions which kind checks them
+    /// and, for `bundle` builds, the rest ones of regions (emitted region ends statement, so
+    /// references can follow it unlike ones of string literals in arbitrary code)
+    pub fn get_interpolation_references(
+        &self,
+        doc: &Document,
+        bundle: bool,
+    ) -> Vec<InterpolationReference> {
+        if doc.parse.str_interpolations.is_empty() {
+            return vec![];
+        }
+
+        let (mut references, mut open_line) = (vec![], None);
+        for t in doc.parse.compressed_tokens.iter() {
+            match t {
+                Token::RegionOpen(s) => open_line = Some(s.line_col.line),
+                Token::RegionClose(s) => {
+                    let Some(open_line) = open_line.take() else {
+                        continue;
+                    };
+
+                    let region = self.get_region_kind(doc, open_line);
+                    let checked = region.is_some_and(|r| r.check_interpolations);
+                    if checked || bundle {
+                        let lines = open_line + 1..s.line_col.line;
+                        let on_lines = doc.interpolations_on_lines(lines).into_iter();
+                        references.extend(on_lines.map(|(range, name)| {
+                            InterpolationReference::new(s.line_col.line, range, name, checked)
+                        }));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        references
+    }
+
     pub fn get_token_types_capabilities(&self) -> Option<&Vec<lsp::SemanticTokenType>> {
         self.token_types_capabilities.get()
     }
@@ -96,3 +262,87 @@ impl State {
         self.destroy_progress(&mut client);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use crate::state::testing::TestProject;
+
+    const SCRIPT: &str = "var a = 1, bb = 2;
+foo(\"x %a y %bb\");
+foo(\"%a\",
+  1);
+#sql
+  select %a, 100%%
+#endsql
+foo(\"%%a %bb\"); // trailing comment
+";
+
+    fn references(project: &TestProject, bundle: bool) -> Vec<(u32, String, bool)> {
+        let doc = project.state.get_doc(&project.uri("main.js")).unwrap();
+        let references = project.state.get_interpolation_references(&doc, bundle);
+        references
+            .into_iter()
+            .map(|r| (r.line, r.name, r.checked))
+            .collect()
+    }
+
+    #[test]
+    fn interpolations_are_referenced_after_region() {
+        let options = json!({ "regions": { "sql": { "checkInterpolations": true } } });
+        let project = TestProject::new(&[("main.js", SCRIPT)], options);
+        project.bundle("main.js");
+
+        assert_eq!(references(&project, true), [(6, "a".into(), true)]);
+        assert_eq!(references(&project, false), [(6, "a".into(), true)]);
+
+        let project = TestProject::new(&[("main.js", SCRIPT)], json!({}));
+        assert_eq!(references(&project, true), [(6, "a".into(), false)]);
+        assert!(references(&project, false).is_empty());
+    }
+
+    #[test]
+    fn interpolations_of_string_literals_are_not_referenced_in_code() {
+        let files = [
+            ("class.js", "class A {\n  x = \"v %a\";\n}\n"),
+            ("if.js", "if (c) alert(\"v %a\");\nelse alert(\"none\");\n"),
+        ];
+        let project = TestProject::new(&files, json!({}));
+
+        for (path, text) in files {
+            let bundle = project.bundle(path);
+            assert!(!bundle.content.contains("typeof"), "{}", bundle.content);
+            for line in text.lines() {
+                assert!(bundle.content.lines().any(|l| l == line), "{line}");
+            }
+        }
+    }
+
+    #[test]
+    fn interpolation_is_forwarded_to_its_reference() {
+        let project = TestProject::new(&[("main.js", SCRIPT)], json!({}));
+        let bundle = project.bundle("main.js");
+        let doc = project.state.get_doc(&project.uri("main.js")).unwrap();
+        let lines = bundle.content.lines().collect::<Vec<_>>();
+
+        let pos = lsp::Position::new(5, 10);
+        let target = doc.interpolation_target(&lsp::Position::new(5, 9));
+        assert_eq!(target, Some(pos));
+
+        let build_pos = bundle.forward_src_position(&pos, &doc.source).unwrap();
+        let text = lines[build_pos.line as usize];
+        let reference = &text[..build_pos.character as usize + 1];
+        assert!(reference.ends_with("typeof a"), "{text}");
+
+        let (source_pos, _) = bundle.forward_build_position(&build_pos).unwrap();
+        assert_eq!(source_pos, pos);
+
+        // interpolation of string literal isn't referenced
+        let pos = lsp::Position::new(2, 6);
+        let build_pos = bundle.forward_src_position(&pos, &doc.source).unwrap();
+        let text = lines[build_pos.line as usize];
+        assert_eq!(&text[build_pos.character as usize..], "a\",");
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+
+use crate::parser::{Declaration, find_declarations};
+use crate::proxy::{JS_FILE_EXT, PROXY_WORKSPACE};
+use crate::state::State;
+use crate::types::{Document, Source, SourceHash};
+
+/// Top-level declarations of indexed project scripts by lowercased name
+#[derive(Default, Debug)]
+pub struct DeclarationIndex {
+    by_name: BTreeMap<String, Vec<(SourceHash, Declaration)>>,
+    /// include path literal (from project root) and indexed names of script
+    scripts: HashMap<SourceHash, (String, Vec<String>)>,
+}
+
+impl DeclarationIndex {
+    fn insert(&mut self, source_hash: SourceHash, literal: String, declarations: Vec<Declaration>) {
+        self.remove(source_hash);
+
+        let mut names = Vec::with_capacity(declarations.len());
+        for d in declarations {
+            let name = d.name.to_lowercase();
+            self.by_name
+                .entry(name.clone())
+                .or_default()
+                .push((source_hash, d));
+            names.push(name);
+        }
+        self.scripts.insert(source_hash, (literal, names));
+    }
+
+    fn remove(&mut self, source_hash: SourceHash) {
+        let Some((_, names)) = self.scripts.remove(&source_hash) else {
+            return;
+        };
+
+        for name in names {
+            if let Some(declarations) = self.by_name.get_mut(&name) {
+                declarations.retain(|(s, _)| *s != source_hash);
+                if declarations.is_empty() {
+                    self.by_name.remove(&name);
+                }
+            }
+        }
+    }
+
+    /// declarations which start with lowercased `prefix` except declarations of `excluded`
+    fn find(&self, prefix: &str, excluded: &HashSet<SourceHash>) -> Vec<(String, Declaration)> {
+        let matched = self.by_name.range(prefix.to_string()..);
+        let matched = matched.take_while(|(name, _)| name.starts_with(prefix));
+        matched
+            .flat_map(|(_, declarations)| declarations)
+            .filter(|(source_hash, _)| !excluded.contains(source_hash))
+            .filter_map(|(source_hash, d)| Some((self.scripts.get(source_hash)?, d)))
+            .map(|((literal, _), d)| (literal.clone(), d.clone()))
+            .collect()
+    }
+}
+
+/// Declarations of project scripts
+impl State {
+    /// reindex declarations of `doc` on next lookup
+    pub fn outdate_declarations(&self, doc: &Document) {
+        self.outdated_declarations.insert(doc.path.to_path_buf());
+    }
+
+    /// top-level declarations which start with `prefix` (case insensitive) of indexed project
+    /// scripts except `sources`, with include path literal (from project root) of each script
+    pub fn find_declarations(
+        &self,
+        prefix: &str,
+        sources: &HashSet<Source>,
+    ) -> Vec<(String, Declaration)> {
+        self.reindex_outdated_declarations();
+
+        let excluded = sources.iter().map(SourceHash::new).collect::<HashSet<_>>();
+        let index = self.declarations.read().unwrap();
+        index.find(&prefix.to_lowercase(), &excluded)
+    }
+
+    pub fn remove_declarations(&self, doc: &Document) {
+        self.outdated_declarations.remove(doc.path.as_path());
+        self.declarations.write().unwrap().remove(doc.source_hash);
+    }
+
+    /// parse declarations of documents changed since last lookup (usually only edited one)
+    fn reindex_outdated_declarations(&self) {
+        let outdated = self.outdated_declarations.iter();
+        let outdated = outdated.map(|p| p.key().clone()).collect::<Vec<_>>();
+        if outdated.is_empty() {
+            return;
+        }
+
+        let project = self.get_project();
+        let proxy_ws = project.join(PROXY_WORKSPACE);
+        let is_indexed = |path: &Path| {
+            let is_js = path.extension().is_some_and(|ext| ext == &JS_FILE_EXT[1..]);
+            is_js && !path.starts_with(&proxy_ws)
+        };
+
+        for path in outdated {
+            self.outdated_declarations.remove(&path);
+            let Some(doc) = self.documents.get(&path).map(|d| d.value().clone()) else {
+                continue;
+            };
+            let Some(literal) = path
+                .strip_prefix(project)
+                .ok()
+                .filter(|_| is_indexed(&path))
+            else {
+                continue;
+            };
+
+            let literal = literal.to_string_lossy().replace('\\', "/");
+            let declarations = find_declarations(&doc.parse_content);
+            let mut index = self.declarations.write().unwrap();
+            index.insert(doc.source_hash, literal, declarations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+
+    use crate::state::testing::TestProject;
+
+    fn names(project: &TestProject, prefix: &str) -> Vec<(String, String)> {
+        let mut found = project.state.find_declarations(prefix, &Default::default());
+        found.sort_unstable_by(|a, b| a.1.name.cmp(&b.1.name));
+        found.into_iter().map(|(l, d)| (l, d.name)).collect()
+    }
+
+    #[test]
+    fn declarations_are_reindexed_after_change() {
+        let files = [
+            (
+                "lib.js",
+                "function fooBar() {}\nvar FOO_BAZ = 1;\nvar other = 2;\n",
+            ),
+            ("main.js", "fooBar();\n"),
+        ];
+        let project = TestProject::new(&files, serde_json::json!({}));
+        let lib = project.uri("lib.js");
+        project.state.get_doc(&lib).unwrap();
+
+        let found = names(&project, "foo");
+        let expected = [("lib.js", "FOO_BAZ"), ("lib.js", "fooBar")];
+        let expected = expected.map(|(l, n)| (l.to_string(), n.to_string()));
+        assert_eq!(found, expected);
+
+        let text = "function fooQux() {}\n".to_string();
+        let change = lsp::TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text,
+        };
+        project.state.set_doc(&lib, &[change]).unwrap();
+        assert_eq!(names(&project, "FOO"), [("lib.js".into(), "fooQux".into())]);
+
+        let doc = project.state.get_doc(&lib).unwrap();
+        let excluded = [(*doc.source).clone()].into_iter().collect();
+        assert!(project.state.find_declarations("foo", &excluded).is_empty());
+
+        project.state.remove_doc(&lib);
+        assert!(names(&project, "foo").is_empty());
+    }
+}
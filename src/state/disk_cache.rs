@@ -0,0 +1,323 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use async_lsp::lsp_types::Url as Uri;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::builder::{Build, BuildCache};
+use crate::parser::{Parse, ParseCache, Token, parse};
+use crate::proxy::PROXY_WORKSPACE;
+use crate::state::State;
+use crate::types::{RegionKind, Source};
+
+/// root of persistent cache in proxy workspace, contains dir per server version
+const DISK_CACHE_DIR: &str = ".cache";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleCacheEntry {
+    /// content hashes of root and all included sources of bundle
+    deps: Vec<(Source, String)>,
+    build: BuildCache,
+}
+
+/// State of persistent cache
+///
+/// parses are keyed by content hash, bundles by root source and validated by content
+/// hashes of all its sources, so any changed, created or deleted dependency invalidates bundle
+impl State {
+    /// enables persistent cache for current server version (caches of other versions are removed)
+    pub fn enable_disk_cache(&self) {
+        let root = self
+            .get_project()
+            .join(PROXY_WORKSPACE)
+            .join(DISK_CACHE_DIR);
+        let version = env!("CARGO_PKG_VERSION");
+
+        for entry in std::fs::read_dir(&root).into_iter().flatten().flatten() {
+            if entry.file_name() != version {
+                let _ = std::fs::remove_dir_all(entry.path());
+            }
+        }
+
+        let dir = root.join(version);
+        let created = ["parse", "bundle"].map(|d| std::fs::create_dir_all(dir.join(d)));
+        match created.iter().find_map(|r| r.as_ref().err()) {
+            Some(err) => tracing::warn!("disk cache disabled: {err}"),
+            None => self.disk_cache.set(dir).expect("disk cache enabled once"),
+        }
+    }
+
+    /// parse of `content` restored from disk cache or parsed (and cached)
+    pub fn parse_with_disk_cache<'a>(&self, content: &'a str) -> Parse<'a> {
+        let is_region_kind = |k: &str| self.is_region_kind(k);
+        let Some(dir) = self.disk_cache.get() else {
+            return parse(content, &is_region_kind);
+        };
+
+        // statements of user-defined regions are tokenized by configured kinds
+        let mut key = content_hash(content);
+        self.sorted_region_kinds()
+            .for_each(|(kind, _)| key.push_str(&format!("#{kind}")));
+        let path = dir.join("parse").join(content_hash(&key) + ".json");
+        let cached = std::fs::read(&path).ok();
+        let cached = cached.and_then(|b| serde_json::from_slice::<ParseCache>(&b).ok());
+
+        if let Some(parse) = cached.and_then(|c| c.restore(content)) {
+            return parse;
+        }
+
+        let parse = parse(content, &is_region_kind);
+        if let Ok(json) = serde_json::to_vec(&ParseCache::new(&parse, content)) {
+            let _ = std::fs::write(path, json);
+        }
+
+        parse
+    }
+
+    /// bundle of document restored from disk cache if none of its sources changed
+    pub fn get_cached_bundle(&self, source_uri: &Uri) -> Option<Build> {
+        let path = self.get_bundle_cache_path(source_uri)?;
+        let entry = std::fs::read(path).ok()?;
+        let entry = serde_json::from_slice::<BundleCacheEntry>(&entry).ok()?;
+        let deps = entry.deps.iter().map(|(s, _)| s).collect::<HashSet<_>>();
+
+        for (source, hash) in entry.deps.iter() {
+            let uri = self
+                .path_to_uri(&self.get_project().join(source.as_str()))
+                .ok()?;
+            let doc = self.get_doc(&uri).ok()?;
+
+            if content_hash(&doc.parse_content) != *hash {
+                return None;
+            }
+
+            // include which was unresolved on caching could be resolved now
+            for t in doc.parse.compressed_tokens.iter() {
+                let Token::IncludePath(sl) = t else {
+                    continue;
+                };
+
+                for dep_path in self.resolve_include_paths(&doc.path, sl.lit).iter() {
+                    let Ok(dep_path) = self
+                        .path_to_uri(dep_path)
+                        .and_then(|u| self.uri_to_path(&u))
+                    else {
+                        continue;
+                    };
+
+                    let dep_source = Source::from_path(&dep_path, self.get_project()).ok()?;
+                    if !deps.contains(&dep_source) {
+                        return None;
+                    }
+                }
+            }
+        }
+
+        entry.build.restore().ok()
+    }
+
+    pub fn set_cached_bundle(&self, source_uri: &Uri, build: &Build) {
+        let Some(path) = self.get_bundle_cache_path(source_uri) else {
+            return;
+        };
+
+        let Ok(doc) = self.get_doc(source_uri) else {
+            return;
+        };
+
+        // sources of shared prelude decide which includes are skipped by bundle
+        let mut deps = vec![(*doc.source).clone()];
+        deps.extend(self.get_sources_stack(build).into_keys());
+
+        let mut hashes = Vec::with_capacity(deps.len());
+        for source in deps {
+            let uri = self.path_to_uri(&self.get_project().join(source.as_str()));
+            let Ok(dep) = uri.and_then(|uri| self.get_doc(&uri)) else {
+                return;
+            };
+            hashes.push((source, content_hash(&dep.parse_content)));
+        }
+
+        let Ok(build) = BuildCache::new(build) else {
+            return;
+        };
+
+        let entry = BundleCacheEntry {
+            deps: hashes,
+            build,
+        };
+        if let Ok(json) = serde_json::to_vec(&entry) {
+            let _ = std::fs::write(path, json);
+        }
+    }
+
+    fn get_bundle_cache_path(&self, source_uri: &Uri) -> Option<PathBuf> {
+        let dir = self.disk_cache.get()?;
+        let doc = self.get_doc(source_uri).ok()?;
+        let mut key = match self.is_shared_prelude() {
+            true => format!("{}#shared-prelude", doc.source),
+            false => doc.source.to_string(),
+        };
+
+        // bundles are emitted by configured defines (defines of default document are
+        // tracked by its content)
+        let defines = self.configured_defines.get().into_iter().flatten();
+        let mut defines = defines.collect::<Vec<_>>();
+        defines.sort_unstable();
+        defines
+            .iter()
+            .for_each(|d| key.push_str(&format!("#define {d}")));
+
+        // and wrapped by configured regions
+        for (kind, region) in self.sorted_region_kinds() {
+            let region = serde_json::to_string(region).unwrap_or_default();
+            key.push_str(&format!("#region {kind} {region}"));
+        }
+        Some(dir.join("bundle").join(content_hash(&key) + ".json"))
+    }
+
+    fn sorted_region_kinds(&self) -> impl Iterator<Item = (&String, &RegionKind)> {
+        let mut regions = self
+            .region_kinds
+            .get()
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        regions.sort_unstable_by_key(|(kind, _)| *kind);
+        regions.into_iter()
+    }
+}
+
+fn content_hash(content: &str) -> String {
+    hex::encode(Sha256::digest(content.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use crate::builder::Build;
+    use crate::parser::parse;
+    use crate::state::testing::TestProject;
+
+    const FILES: [(&str, &str); 3] = [
+        (
+            "main.js",
+            "#include <a.js>\n#include <b.js>\nvar m = #text\n  %a\n#endtext;\n",
+        ),
+        ("a.js", "#include <b.js>\nvar a = 1;\n"),
+        ("b.js", "var b = `x\n${1}`;\n"),
+    ];
+
+    fn cached_files(project: &TestProject, dir: &str) -> usize {
+        let dir = project.state.disk_cache.get().unwrap().join(dir);
+        std::fs::read_dir(dir).unwrap().count()
+    }
+
+    /// builds have the same content, stacks, issues and mappings of every build position
+    fn assert_build_eq(restored: &Build, build: &Build) {
+        assert_eq!(restored.content, build.content);
+        assert_eq!(restored.uri, build.uri);
+        assert_eq!(restored.sources_stack, build.sources_stack);
+        let issues = |b: &Build| format!("{:?}", b.include_issues);
+        assert_eq!(issues(restored), issues(build));
+
+        for (line, text) in build.content.lines().enumerate() {
+            for col in 0..=text.len() as u32 {
+                let pos = lsp::Position::new(line as u32, col);
+                let forwarded = restored.forward_build_position(&pos);
+                assert_eq!(forwarded, build.forward_build_position(&pos), "{pos:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_is_restored_from_disk_cache() {
+        let project = TestProject::new(&FILES, json!({}));
+        let st = &project.state;
+        st.enable_disk_cache();
+
+        let content = FILES[0].1;
+        let parsed = format!("{:?}", parse(content, &|_| false));
+        assert_eq!(format!("{:?}", st.parse_with_disk_cache(content)), parsed);
+        assert_eq!(cached_files(&project, "parse"), 1);
+
+        // the same content is restored instead of parsed again
+        let restored = st.parse_with_disk_cache(content);
+        assert_eq!(format!("{restored:?}"), parsed);
+        assert_eq!(cached_files(&project, "parse"), 1);
+    }
+
+    #[test]
+    fn bundle_is_restored_from_disk_cache() {
+        let project = TestProject::new(&FILES, json!({}));
+        let st = &project.state;
+        st.enable_disk_cache();
+        let uri = project.uri("main.js");
+
+        assert!(st.get_cached_bundle(&uri).is_none());
+        let build = project.bundle("main.js");
+        assert!(!build.include_issues.is_empty());
+        st.set_cached_bundle(&uri, &build);
+        assert_eq!(cached_files(&project, "bundle"), 1);
+
+        let restored = st.get_cached_bundle(&uri).unwrap();
+        assert_build_eq(&restored, &build);
+    }
+
+    #[test]
+    fn bundle_cache_is_invalidated_by_changed_dependency() {
+        let project = TestProject::new(&FILES, json!({}));
+        let st = &project.state;
+        st.enable_disk_cache();
+        let uri = project.uri("main.js");
+        st.set_cached_bundle(&uri, &project.bundle("main.js"));
+
+        let set_text = |path: &str, text: &str| {
+            let change = lsp::TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: text.into(),
+            };
+            st.set_doc(&project.uri(path), &[change]).unwrap();
+        };
+
+        // content hash of nested dependency is changed
+        set_text("b.js", "var b = 2;\n");
+        assert!(st.get_cached_bundle(&uri).is_none());
+
+        // and changed back
+        set_text("b.js", FILES[2].1);
+        assert!(st.get_cached_bundle(&uri).is_some());
+
+        // include of created file is resolved now
+        set_text("a.js", "#include <c.js>\nvar a = 1;\n");
+        st.set_cached_bundle(&uri, &project.bundle("main.js"));
+        assert!(st.get_cached_bundle(&uri).is_some());
+        std::fs::write(project.root.join("c.js"), "var c = 1;\n").unwrap();
+        st.clear_include_paths_caches();
+        assert!(st.get_cached_bundle(&uri).is_none());
+    }
+
+    fn bundle_cache_key(options: serde_json::Value) -> String {
+        let project = TestProject::new(&[("main.js", "var a = 1;\n")], options);
+        project.state.enable_disk_cache();
+        let path = project.state.get_bundle_cache_path(&project.uri("main.js"));
+        path.unwrap()
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn bundle_cache_is_keyed_by_regions() {
+        let json_region = |prefix: &str| json!({ "regions": { "json": { "prefix": prefix } } });
+        let key = bundle_cache_key(json_region("JSON.parse("));
+        assert_eq!(key, bundle_cache_key(json_region("JSON.parse(")));
+        assert_ne!(key, bundle_cache_key(json_region("JSON.stringify(")));
+        assert_ne!(key, bundle_cache_key(json!({})));
+    }
+}
@@ -6,7 +6,7 @@ use async_lsp::lsp_types as lsp;
 use async_lsp::lsp_types::Url as Uri;
 use ropey::Rope;
 
-use crate::parser::{Parse, parse};
+use crate::parser::{Edit, Parse, lines_count, parse, reparse};
 use crate::proxy::{Canonicalize, PROXY_WORKSPACE};
 use crate::state::{BuildStorage, State};
 use crate::types::{Document, DocumentDeclarationStatement, DocumentLinkStatement};
@@ -46,9 +46,10 @@ impl State {
                 transpile_uri: transpiled_doc_uri.into(),
 
                 buffer: Rope::new(),
+                version: 0,
                 parse: Parse::default().into(),
                 parse_content: String::new().into(),
-                transpile_hash: (&vec![], None).into(),
+                transpile_hash: (&vec![], None, &[][..]).into(),
 
                 decl_stmt: DocumentDeclarationStatement::create(&source, &source_ident).into(),
                 link_stmt: DocumentLinkStatement::create(&source, &source_ident).into(),
@@ -64,36 +65,92 @@ impl State {
         let patch_doc_content = |doc: &mut RefMut<'_>, content: &str| {
             let content = Arc::new(content.to_string());
             let content_ref = content.clone();
-            let parse = unsafe { transmute::<Parse<'_>, Parse<'static>>(parse(&content_ref)) };
+            let parse = self.parse_with_disk_cache(&content_ref);
+            let parse = unsafe { transmute::<Parse<'_>, Parse<'static>>(parse) };
 
             doc.parse = parse.into();
             doc.parse_content = content;
+            doc.version = doc.version.wrapping_add(1);
         };
 
         if changes.len() == 1 && changes[0].range.is_none() {
             let new_text = changes[0].text.as_str();
+            self.shift_published_diagnostics(&path, &changes[0]);
             doc.buffer = Rope::from_str(new_text);
             patch_doc_content(&mut doc, new_text);
-            doc.transpile_hash = (doc.parse.compressed_tokens.as_ref(), None).into();
+            let refs = self.get_interpolation_references(&doc, true);
+            doc.transpile_hash = (doc.parse.compressed_tokens.as_ref(), None, &refs[..]).into();
+            self.set_doc_includes(&doc);
+            self.set_doc_defines(&doc);
+            self.outdate_declarations(&doc);
             return Ok(());
         }
 
+        // parsed text is patched by changes (instead of copying the rope), so the range of
+        // changed bytes is known without comparing texts
+        let mut content = String::clone(&doc.parse_content);
+        let mut edit = None::<Edit>;
         for change in changes {
             let r = change.range.as_ref().unwrap();
             let text = change.text.as_str();
             let start = doc.buffer.line_to_char(r.start.line as usize) + r.start.character as usize;
             let end = doc.buffer.line_to_char(r.end.line as usize) + r.end.character as usize;
+            let bytes = doc.buffer.char_to_byte(start)..doc.buffer.char_to_byte(end);
 
             doc.buffer.remove(start..end);
             doc.buffer.insert(start, text);
+            content.replace_range(bytes.clone(), text);
+
+            self.shift_published_diagnostics(&path, change);
+
+            let (lines, len) = (lines_count(text), text.len());
+            let next = Edit::new(bytes.start, bytes.end, len, r.start.line, r.end.line, lines);
+            edit = Some(edit.map_or(next, |e| e.then(next)));
         }
 
-        let full_text = &doc.buffer.to_string();
-        patch_doc_content(&mut doc, full_text);
-        doc.transpile_hash = (doc.parse.compressed_tokens.as_ref(), changes.into()).into();
+        let content = Arc::new(content);
+        let content_ref = content.clone();
+        let is_region_kind = |k: &str| self.is_region_kind(k);
+        let parse = match edit {
+            Some(edit) => reparse(
+                &doc.parse,
+                &doc.parse_content,
+                &content_ref,
+                edit,
+                &is_region_kind,
+            ),
+            None => parse(&content_ref, &is_region_kind),
+        };
+        doc.parse = unsafe { transmute::<Parse<'_>, Parse<'static>>(parse) }.into();
+        doc.parse_content = content;
+        doc.version = doc.version.wrapping_add(1);
+        let refs = self.get_interpolation_references(&doc, true);
+        let tokens = doc.parse.compressed_tokens.as_ref();
+        doc.transpile_hash = (tokens, changes.into(), &refs[..]).into();
+        self.set_doc_includes(&doc);
+        self.set_doc_defines(&doc);
+        self.outdate_declarations(&doc);
         Ok(())
     }
 
+    /// forget document which was deleted from disk
+    pub fn remove_doc(&self, source_uri: &Uri) {
+        let path = match self.uri_to_path(source_uri) {
+            Ok(path) => (*path).clone(),
+            Err(_) => match source_uri.to_file_path() {
+                Ok(path) => path,
+                Err(_) => return,
+            },
+        };
+
+        if let Some((_, doc)) = self.documents.remove(&path) {
+            self.remove_emit_segments(doc.source_hash);
+            self.remove_declarations(&doc);
+            self.remove_published_diagnostics(&doc);
+        }
+        self.remove_doc_includes(&path);
+    }
+
     /// will create uninitialized documents too
     pub fn get_doc(&self, source_uri: &Uri) -> anyhow::Result<Document> {
         let path = self.uri_to_path(source_uri)?;
@@ -118,6 +175,15 @@ impl State {
         self.get_doc(source_uri)
     }
 
+    /// on-disk path of loaded document of `source` (sources are lowercased paths)
+    pub fn get_source_path(&self, source: &Source) -> PathBuf {
+        let doc = self.documents.iter().find(|d| *d.source == *source);
+        match doc {
+            Some(doc) => doc.path.to_path_buf(),
+            None => self.get_project().join(source.as_str()),
+        }
+    }
+
     pub fn get_doc_source_hash(&self, source_uri: &Uri) -> anyhow::Result<SourceHash> {
         let path = self.uri_to_path(source_uri)?;
         let path = &(*path).clone();
@@ -0,0 +1,233 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::RwLockReadGuard;
+
+use async_lsp::lsp_types as lsp;
+use derive_more::Constructor;
+
+use crate::parser::Token;
+use crate::state::{State, is_glob};
+use crate::types::Document;
+
+/// Include statement of includer document
+#[derive(Debug, Clone, Constructor)]
+pub struct IncludeEdge {
+    /// canonicalized path of resolved include (or normalized path if file doesn't exist)
+    pub target: PathBuf,
+    /// range of [`Token::IncludePath`] (with brackets) in includer document
+    pub range: lsp::Range,
+}
+
+/// Project-wide graph of include statements between documents
+#[derive(Debug, Default)]
+pub struct IncludeGraph {
+    includes: HashMap<PathBuf, Vec<IncludeEdge>>,
+    included_by: HashMap<PathBuf, HashSet<PathBuf>>,
+    /// includers with glob or unresolved includes, which edges are changed once any script
+    /// is created or deleted
+    volatile: HashSet<PathBuf>,
+}
+
+impl IncludeGraph {
+    pub fn set_includes(&mut self, includer: PathBuf, edges: Vec<IncludeEdge>, volatile: bool) {
+        self.remove_includes(&includer);
+        if volatile {
+            self.volatile.insert(includer.clone());
+        }
+
+        for edge in edges.iter() {
+            let includers = self.included_by.entry(edge.target.clone()).or_default();
+            includers.insert(includer.clone());
+        }
+
+        self.includes.insert(includer, edges);
+    }
+
+    pub fn remove_includes(&mut self, includer: &PathBuf) {
+        self.volatile.remove(includer);
+        for edge in self.includes.remove(includer).unwrap_or_default() {
+            if let Some(includers) = self.included_by.get_mut(&edge.target) {
+                includers.remove(includer);
+                if includers.is_empty() {
+                    self.included_by.remove(&edge.target);
+                }
+            }
+        }
+    }
+
+    /// include statements of direct includers of `target`
+    pub fn get_includers(&self, target: &PathBuf) -> Vec<(PathBuf, lsp::Range)> {
+        let Some(includers) = self.included_by.get(target) else {
+            return vec![];
+        };
+
+        let mut sites = vec![];
+        for includer in includers {
+            let edges = self.includes.get(includer).into_iter().flatten();
+            let edges = edges.filter(|e| &e.target == target);
+            sites.extend(edges.map(|e| (includer.clone(), e.range)));
+        }
+
+        sites.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(a.1.start.cmp(&b.1.start)));
+        sites
+    }
+
+    /// all documents included by `includer` directly or by its dependencies (in include order)
+    pub fn get_transitive_includes(&self, includer: &PathBuf) -> Vec<PathBuf> {
+        let mut visited = HashSet::from([includer.clone()]);
+        let mut includes = vec![];
+        let mut stack = vec![includer.clone()];
+
+        while let Some(path) = stack.pop() {
+            let edges = self.includes.get(&path).into_iter().flatten();
+            for edge in edges.rev() {
+                if visited.insert(edge.target.clone()) {
+                    stack.push(edge.target.clone());
+                }
+            }
+
+            if &path != includer {
+                includes.push(path);
+            }
+        }
+
+        includes
+    }
+}
+
+/// State of include graph
+///
+/// graph is filled by project indexing (each indexed document passes through `set_doc`)
+/// and kept up to date by document changes and watched files changes: includes which
+/// could be resolved differently after defines change or script creation and deletion
+/// are resolved again on next graph access
+impl State {
+    pub fn get_include_graph(&self) -> RwLockReadGuard<'_, IncludeGraph> {
+        self.update_outdated_includes();
+        self.include_graph.read().unwrap()
+    }
+
+    pub fn set_doc_includes(&self, doc: &Document) {
+        self.outdated_includes.remove(doc.path.as_path());
+        let (edges, volatile) = self.get_include_edges(doc);
+        let mut graph = self.include_graph.write().unwrap();
+        graph.set_includes((*doc.path).clone(), edges, volatile);
+    }
+
+    pub fn remove_doc_includes(&self, path: &Path) {
+        self.outdated_includes.remove(path);
+        let mut graph = self.include_graph.write().unwrap();
+        graph.remove_includes(&path.to_path_buf());
+    }
+
+    /// includes of inactive branches are skipped, so all includers are outdated by defines
+    pub fn outdate_includes_by_defines(&self) {
+        let graph = self.include_graph.read().unwrap();
+        let includers = graph.includes.keys().cloned();
+        includers.for_each(|p| _ = self.outdated_includes.insert(p));
+    }
+
+    /// glob and unresolved includes and includes of created or deleted `script` are outdated
+    pub fn outdate_includes_by_script(&self, script: &Path) {
+        let graph = self.include_graph.read().unwrap();
+        let includers = graph.included_by.get(script).into_iter().flatten();
+        let includers = includers.chain(graph.volatile.iter()).cloned();
+        includers.for_each(|p| _ = self.outdated_includes.insert(p));
+    }
+
+    fn update_outdated_includes(&self) {
+        let outdated = self.outdated_includes.iter();
+        let outdated = outdated.map(|p| p.key().clone()).collect::<Vec<_>>();
+
+        for path in outdated {
+            match self.documents.get(&path).map(|d| d.value().clone()) {
+                Some(doc) => self.set_doc_includes(&doc),
+                None => self.remove_doc_includes(&path),
+            }
+        }
+    }
+
+    /// edges of includes of active branches and whether any include is glob or unresolved
+    fn get_include_edges(&self, doc: &Document) -> (Vec<IncludeEdge>, bool) {
+        let conditionals = self.get_conditionals(doc);
+        let mut edges = vec![];
+        let mut volatile = false;
+
+        for (idx, t) in doc.parse.compressed_tokens.iter().enumerate() {
+            let Token::IncludePath(sl) = t else {
+                continue;
+            };
+
+            if conditionals.is_inactive(idx) {
+                continue;
+            }
+
+            let lc = &sl.line_col;
+            let start = lsp::Position::new(lc.line, lc.col);
+            let end = lsp::Position::new(lc.line, lc.col + sl.lit.chars().count() as u32 + 2);
+            volatile |= is_glob(sl.lit);
+
+            for dep_path in self.resolve_include_paths(&doc.path, sl.lit).iter() {
+                let target = match self.path_to_uri(dep_path) {
+                    Ok(uri) => self.uri_to_path(&uri).map(|p| (*p).clone()),
+                    Err(err) => Err(err),
+                };
+
+                volatile |= target.is_err();
+                let target = target.unwrap_or(dep_path.clone());
+                edges.push(IncludeEdge::new(target, lsp::Range::new(start, end)));
+            }
+        }
+
+        (edges, volatile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::state::testing::TestProject;
+
+    fn includers(project: &TestProject, target: &str) -> Vec<(String, u32)> {
+        let graph = project.state.get_include_graph();
+        let includers = graph.get_includers(&project.root.join(target)).into_iter();
+        let includers = includers.map(|(p, range)| {
+            let path = p.strip_prefix(&project.root).unwrap().to_string_lossy();
+            (path.replace('\\', "/"), range.start.line)
+        });
+        includers.collect()
+    }
+
+    #[test]
+    fn includes_of_inactive_branches_are_skipped() {
+        let main = "#ifdef A\n#include <a.js>\n#else\n#include <b.js>\n#endif\n";
+        let files = [("main.js", main), ("a.js", ""), ("b.js", "")];
+        let project = TestProject::new(&files, json!({ "defines": ["A"] }));
+        project.state.get_doc(&project.uri("main.js")).unwrap();
+
+        assert_eq!(includers(&project, "a.js"), [("main.js".into(), 1)]);
+        assert!(includers(&project, "b.js").is_empty());
+    }
+
+    #[test]
+    fn glob_and_unresolved_includes_are_resolved_after_script_is_created() {
+        let main = "#include <util.js>\n#include <lib/*.js>\n#include <other.js>\n";
+        let files = [("main.js", main), ("other.js", "")];
+        let project = TestProject::new(&files, json!({}));
+        project.state.get_doc(&project.uri("main.js")).unwrap();
+        assert!(includers(&project, "lib/a.js").is_empty());
+
+        for script in ["util.js", "lib/a.js"] {
+            let path = project.root.join(script);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, "").unwrap();
+            project.state.clear_include_paths_caches();
+            project.state.outdate_includes_by_script(&path);
+        }
+
+        assert_eq!(includers(&project, "util.js"), [("main.js".into(), 0)]);
+        assert_eq!(includers(&project, "lib/a.js"), [("main.js".into(), 1)]);
+        assert_eq!(includers(&project, "other.js"), [("main.js".into(), 2)]);
+    }
+}
@@ -0,0 +1,386 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+use async_lsp::lsp_types as lsp;
+
+use crate::builder::EMIT_FILE_EXT;
+use crate::parser::Token;
+use crate::proxy::{JS_FILE_EXT, PROXY_WORKSPACE};
+use crate::state::State;
+use crate::types::{Document, IncludeRoot};
+
+const INCLUDE_SUGGESTIONS_LIMIT: usize = 5;
+const MAX_TYPOS_IN_FILE_NAME: usize = 2;
+
+/// State of include statements resolving
+impl State {
+    /// resolves include path literal by the first root containing file (see
+    /// [`crate::types::IncludeRoots`]), falls back to the first tried root if file is missing
+    pub fn resolve_include_path(
+        &self,
+        path_from: &Path,
+        path_literal: &str,
+    ) -> (PathBuf, IncludeRoot) {
+        let mut candidates = self.include_candidates(path_from, path_literal);
+        match candidates.iter().position(|(p, _)| p.is_file()) {
+            Some(idx) => candidates.swap_remove(idx),
+            None => candidates.swap_remove(0),
+        }
+    }
+
+    /// resolved paths of include: files matched by glob pattern in sorted order (matched by
+    /// the first root with any match) or single resolved path of plain include
+    ///
+    /// unmatched glob is resolved to path of pattern itself, so it is unresolved as plain one
+    pub fn resolve_include_paths(&self, path_from: &Path, path_literal: &str) -> Arc<Vec<PathBuf>> {
+        if !is_glob(path_literal) {
+            return vec![(*self.path_resolver(path_from, path_literal)).clone()].into();
+        }
+
+        let key = (path_from.into(), path_literal.to_string());
+        if let Some(paths) = self.include_globs_cache.get(&key) {
+            return paths.clone();
+        }
+
+        let proxy_ws = self.get_project().join(PROXY_WORKSPACE);
+        let candidates = self.include_candidates(path_from, path_literal);
+        let matched = candidates.iter().map(|(pattern, _)| {
+            let files = expand_glob(pattern).into_iter();
+            let files = files.filter(|f| f != path_from && !f.starts_with(&proxy_ws));
+            files.collect::<Vec<_>>()
+        });
+
+        let paths = matched.into_iter().find(|files| !files.is_empty());
+        let paths: Arc<Vec<_>> = paths.unwrap_or(vec![candidates[0].0.clone()]).into();
+
+        self.include_globs_cache.insert(key, paths.clone());
+        paths
+    }
+
+    /// matched globs and paths resolved by roots are outdated once any script is created
+    /// or deleted
+    pub fn clear_include_paths_caches(&self) {
+        self.include_globs_cache.clear();
+        self.path_resolver_cache.clear();
+        self.project_scripts_cache.write().unwrap().take();
+    }
+
+    /// scripts of project (except proxy workspace) walked once until any script is created
+    /// or deleted
+    fn get_project_scripts(&self) -> Arc<Vec<PathBuf>> {
+        if let Some(scripts) = self.project_scripts_cache.read().unwrap().as_ref() {
+            return scripts.clone();
+        }
+
+        let project = self.get_project();
+        let proxy_ws = project.join(PROXY_WORKSPACE);
+        let scripts = ignore::Walk::new(project).flatten().filter_map(|entry| {
+            let path = entry.path();
+            let is_js = path.extension().is_some_and(|ext| ext == &JS_FILE_EXT[1..]);
+            (is_js && !path.starts_with(&proxy_ws)).then(|| entry.into_path())
+        });
+
+        let scripts = Arc::new(scripts.collect::<Vec<_>>());
+        *self.project_scripts_cache.write().unwrap() = Some(scripts.clone());
+        scripts
+    }
+
+    /// paths of include literal by roots in resolving order
+    fn include_candidates(
+        &self,
+        path_from: &Path,
+        path_literal: &str,
+    ) -> Vec<(PathBuf, IncludeRoot)> {
+        let project = self.get_project();
+        let path = path_literal.replace("\\\\", "/").replace("\\", "/");
+
+        if is_relative(&path) {
+            let resolved = normalize(&path_from.parent().unwrap().join(path));
+            return vec![(resolved, IncludeRoot::Relative)];
+        }
+
+        let mut candidates = vec![];
+        let roots = self.get_include_roots();
+
+        for (prefix, dir) in roots.iter().flat_map(|r| r.aliases.iter()) {
+            if let Some(rest) = path.strip_prefix(prefix.as_str()) {
+                let root = IncludeRoot::Alias(prefix.clone(), dir.clone());
+                let path = project.join(dir).join(rest.trim_start_matches('/'));
+                candidates.push((normalize(&path), root));
+            }
+        }
+
+        candidates.push((normalize(&project.join(&path)), IncludeRoot::Project));
+
+        for dir in roots.iter().flat_map(|r| r.search_paths.iter()) {
+            let root = IncludeRoot::SearchPath(dir.clone());
+            candidates.push((normalize(&project.join(dir).join(&path)), root));
+        }
+
+        candidates
+    }
+
+    /// returns range (with brackets) and literal of include paths which are not resolved to file
+    pub fn get_unresolved_includes(&self, doc: &Document) -> Vec<(lsp::Range, String)> {
+        let mut unresolved = vec![];
+        let conditionals = self.get_conditionals(doc);
+
+        for (idx, t) in doc.parse.compressed_tokens.iter().enumerate() {
+            let Token::IncludePath(sl) = t else {
+                continue;
+            };
+
+            if conditionals.is_inactive(idx) {
+                continue;
+            }
+
+            let dep_paths = self.resolve_include_paths(&doc.path, sl.lit);
+            if dep_paths.iter().any(|p| p.is_file()) {
+                continue;
+            }
+
+            let lc = &sl.line_col;
+            let start = lsp::Position::new(lc.line, lc.col);
+            let end = lsp::Position::new(lc.line, lc.col + sl.lit.chars().count() as u32 + 2);
+            unresolved.push((lsp::Range::new(start, end), sl.lit.to_string()));
+        }
+
+        unresolved
+    }
+
+    /// returns existing project files which are nearest to unresolved `path_literal` by
+    /// fuzzy match, formatted in the same style (relative or project root) as literal
+    pub fn suggest_include_paths(&self, path_from: &Path, path_literal: &str) -> Vec<String> {
+        use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+        use nucleo_matcher::{Matcher, Utf32Str};
+
+        let project = self.get_project();
+        let literal = path_literal.replace('\\', "/");
+        let is_relative = literal.starts_with("./") || literal.starts_with("../");
+        let file_name = literal.rsplit('/').next().unwrap_or(&literal);
+
+        let pattern = Pattern::parse(&literal, CaseMatching::Smart, Normalization::Smart);
+        let file_pattern = Pattern::parse(file_name, CaseMatching::Smart, Normalization::Smart);
+        let matcher = &mut Matcher::default();
+        let mut buf = vec![];
+        let mut suggestions = vec![];
+
+        for path in self.get_project_scripts().iter() {
+            if path == path_from {
+                continue;
+            }
+
+            let candidate = match is_relative {
+                true => relative_literal(path_from.parent().unwrap(), path),
+                false => path
+                    .strip_prefix(project)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+            };
+            let candidate_name = candidate.rsplit('/').next().unwrap_or(&candidate);
+
+            let score = pattern.score(Utf32Str::new(&candidate, &mut buf), matcher);
+            let score = score.or_else(|| {
+                let haystack = Utf32Str::new(candidate_name, &mut buf);
+                file_pattern.score(haystack, matcher).map(|s| s / 2)
+            });
+            let score = score.or_else(|| {
+                let distance = edit_distance(file_name, candidate_name);
+                (distance <= MAX_TYPOS_IN_FILE_NAME).then_some(0)
+            });
+
+            if let Some(score) = score {
+                suggestions.push((score, candidate));
+            }
+        }
+
+        suggestions.sort_unstable_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+        suggestions.truncate(INCLUDE_SUGGESTIONS_LIMIT);
+        suggestions.into_iter().map(|(_, c)| c).collect()
+    }
+}
+
+pub fn is_glob(path_literal: &str) -> bool {
+    path_literal.contains(['*', '?', '[', '{'])
+}
+
+/// scripts matched by absolute glob `pattern` (walk starts from its static directory)
+fn expand_glob(pattern: &Path) -> Vec<PathBuf> {
+    use ignore::WalkBuilder;
+    use ignore::overrides::OverrideBuilder;
+
+    let components = pattern.components().collect::<Vec<_>>();
+    let is_glob_component = |c: &Component| is_glob(&c.as_os_str().to_string_lossy());
+    let static_len = components.iter().position(is_glob_component);
+    let static_len = static_len.unwrap_or(components.len());
+    let dir = components[..static_len].iter().collect::<PathBuf>();
+    let glob = components[static_len..].iter().collect::<PathBuf>();
+    let glob = format!("/{}", glob.to_string_lossy().replace('\\', "/"));
+
+    let mut overrides = OverrideBuilder::new(&dir);
+    let Ok(overrides) = overrides.add(&glob).and_then(|o| o.build()) else {
+        return vec![];
+    };
+
+    let walk = WalkBuilder::new(&dir)
+        .overrides(overrides)
+        .build()
+        .flatten();
+    let mut files = walk
+        .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+        .map(|e| e.into_path())
+        .filter(|p| {
+            let name = p.to_string_lossy();
+            name.ends_with(JS_FILE_EXT) && !name.ends_with(EMIT_FILE_EXT)
+        })
+        .collect::<Vec<_>>();
+
+    files.sort();
+    files
+}
+
+fn is_relative(path: &str) -> bool {
+    path.starts_with("./") || path.starts_with("../")
+}
+
+#[allow(clippy::unit_arg)]
+fn normalize(path: &Path) -> PathBuf {
+    let mut buf = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => buf.pop().eq(&false).then(|| buf.push("..")),
+            Component::CurDir => None,
+            _ => buf.push(component.as_os_str()).into(),
+        };
+    }
+    buf
+}
+
+/// `./`-prefixed path of `to` relative to `from_dir` with forward slashes
+pub fn relative_literal(from_dir: &Path, to: &Path) -> String {
+    let from = from_dir.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut rel = PathBuf::new();
+    (common..from.len()).for_each(|_| rel.push(Component::ParentDir));
+    to[common..].iter().for_each(|c| rel.push(c));
+
+    let rel = rel.to_string_lossy().replace('\\', "/");
+    match rel.starts_with("../") {
+        true => rel,
+        false => format!("./{rel}"),
+    }
+}
+
+/// levenshtein distance (fallback of fuzzy match for swapped chars)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + (ca != *cb) as usize;
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::state::testing::TestProject;
+
+    #[test]
+    fn include_is_resolved_again_after_script_is_created() {
+        let files = [("main.js", "#include <util.js>"), ("lib/util.js", "")];
+        let project = TestProject::new(&files, json!({ "includePaths": ["lib"] }));
+        let main = project.root.join("main.js");
+
+        let resolved = project.state.path_resolver(&main, "util.js");
+        assert_eq!(*resolved, project.root.join("lib/util.js"));
+
+        // project root is tried before search paths
+        std::fs::write(project.root.join("util.js"), "").unwrap();
+        let resolved = project.state.path_resolver(&main, "util.js");
+        assert_eq!(*resolved, project.root.join("lib/util.js"));
+
+        project.state.clear_include_paths_caches();
+        let resolved = project.state.path_resolver(&main, "util.js");
+        assert_eq!(*resolved, project.root.join("util.js"));
+    }
+
+    #[test]
+    fn include_suggestions_are_walked_again_after_script_is_created() {
+        let files = [
+            ("main.js", "#include <utils/mth.js>"),
+            ("utils/math.js", ""),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        let main = project.root.join("main.js");
+        let suggest = || project.state.suggest_include_paths(&main, "utils/mth.js");
+        assert_eq!(suggest(), ["utils/math.js"]);
+
+        std::fs::write(project.root.join("utils/mth2.js"), "").unwrap();
+        assert_eq!(suggest(), ["utils/math.js"]);
+
+        project.state.clear_include_paths_caches();
+        assert_eq!(suggest(), ["utils/mth2.js", "utils/math.js"]);
+    }
+
+    #[test]
+    fn glob_is_expanded_into_sorted_scripts() {
+        let files = [
+            ("main.js", "#include <utils/*.js>"),
+            ("utils/b.js", ""),
+            ("utils/a.js", ""),
+            ("utils/readme.md", ""),
+            ("utils/c.emitted.js", ""),
+            ("utils/nested/d.js", ""),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        let main = project.root.join("main.js");
+        let paths = |lit| project.state.resolve_include_paths(&main, lit);
+
+        let expected = ["utils/a.js", "utils/b.js"].map(|p| project.root.join(p));
+        assert_eq!(*paths("utils/*.js"), expected);
+
+        let expected = ["utils/a.js", "utils/b.js", "utils/nested/d.js"];
+        assert_eq!(
+            *paths("utils/**/*.js"),
+            expected.map(|p| project.root.join(p))
+        );
+        assert_eq!(
+            *paths("./utils/{a,b}.js"),
+            [
+                project.root.join("utils/a.js"),
+                project.root.join("utils/b.js")
+            ]
+        );
+    }
+
+    #[test]
+    fn glob_is_matched_by_first_root_with_matches() {
+        let files = [
+            ("main.js", ""),
+            ("lib/utils/a.js", ""),
+            ("vendor/utils/b.js", ""),
+            ("x.js", ""),
+        ];
+        let options = json!({ "includePaths": ["lib", "vendor"] });
+        let project = TestProject::new(&files, options);
+        let main = project.root.join("main.js");
+        let paths = |lit| project.state.resolve_include_paths(&main, lit);
+
+        assert_eq!(*paths("utils/*.js"), [project.root.join("lib/utils/a.js")]);
+        // including script is not matched and unmatched glob is resolved to pattern itself
+        assert_eq!(*paths("*.js"), [project.root.join("x.js")]);
+        assert_eq!(*paths("none/*.js"), [project.root.join("none/*.js")]);
+    }
+}
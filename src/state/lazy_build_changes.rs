@@ -1,4 +1,4 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use async_lsp::lsp_types::Url as Uri;
 use async_lsp::{LanguageServer, ServerSocket, lsp_types as lsp};
@@ -36,8 +36,8 @@ impl State {
         };
         let path = (*path).clone();
 
-        let commit = |s: &mut ServerSocket, storage: &UnforwardedBuildChanges| {
-            let Some(changes) = storage.remove(&path).map(|e| e.1) else {
+        let commit = |s: &mut ServerSocket, storage: &UnforwardedBuildChanges, path: &PathBuf| {
+            let Some(changes) = storage.remove(path).map(|e| e.1) else {
                 return;
             };
 
@@ -48,8 +48,15 @@ impl State {
 
         self.forward();
 
-        commit(s, &self.uncommitted_bundle_changes);
-        commit(s, &self.uncommitted_transpile_changes);
+        // bundle refers to symbols of shared prelude
+        if self.is_shared_prelude()
+            && let Ok(prelude_path) = self.uri_to_path(&self.get_default_doc())
+        {
+            commit(s, &self.uncommitted_bundle_changes, &prelude_path);
+        }
+
+        commit(s, &self.uncommitted_bundle_changes, &path);
+        commit(s, &self.uncommitted_transpile_changes, &path);
     }
 }
 
@@ -57,37 +64,48 @@ impl State {
     fn forward(&self) {
         use rayon::prelude::*;
 
+        // other bundles skip sources of shared prelude, so it is rebuilt first
+        if self.is_shared_prelude()
+            && let Ok(prelude_path) = self.uri_to_path(&self.get_default_doc())
+            && let Some((path, changes)) = self.unforwarded_doc_changes.remove(&*prelude_path)
+        {
+            self.forward_doc(&path, &changes);
+        }
+
         self.unforwarded_doc_changes.par_iter().for_each(|entry| {
-            let (path, changes) = (entry.key(), entry.value());
-            let doc_uri = &self.path_to_uri(path).unwrap();
-            for (doc_changes, transpile_changed) in changes {
-                let transpile_changed = *transpile_changed;
-
-                let transpile_task = || {
-                    if let Some(t) = self.get_transpile(doc_uri) {
-                        let changes = self.forward_changes(&t, doc_changes, transpile_changed);
-                        let t_new = self.set_transpile(doc_uri).unwrap();
-                        let p = self.forward_params(changes, &t_new, transpile_changed);
-                        self.add_forwarded_changes(doc_uri, p, &self.uncommitted_transpile_changes);
-                    }
-                };
-
-                let bundle_task = || {
-                    if let Some(b) = self.get_bundle(doc_uri) {
-                        let changes = self.forward_changes(&b, doc_changes, transpile_changed);
-                        let b_new = self.set_bundle(doc_uri).unwrap();
-                        let p = self.forward_params(changes, &b_new, transpile_changed);
-                        self.add_forwarded_changes(doc_uri, p, &self.uncommitted_bundle_changes);
-                    }
-                };
-
-                rayon::join(transpile_task, bundle_task);
-            }
+            self.forward_doc(entry.key(), entry.value());
         });
 
         self.unforwarded_doc_changes.clear();
     }
 
+    fn forward_doc(&self, path: &Path, changes: &[(lsp::DidChangeTextDocumentParams, bool)]) {
+        let doc_uri = &self.path_to_uri(path).unwrap();
+        for (doc_changes, transpile_changed) in changes {
+            let transpile_changed = *transpile_changed;
+
+            let transpile_task = || {
+                if let Some(t) = self.get_transpile(doc_uri) {
+                    let changes = self.forward_changes(&t, doc_changes, transpile_changed);
+                    let t_new = self.set_transpile(doc_uri).unwrap();
+                    let p = self.forward_params(changes, &t_new, transpile_changed);
+                    self.add_forwarded_changes(doc_uri, p, &self.uncommitted_transpile_changes);
+                }
+            };
+
+            let bundle_task = || {
+                if let Some(b) = self.get_bundle(doc_uri) {
+                    let changes = self.forward_changes(&b, doc_changes, transpile_changed);
+                    let b_new = self.set_bundle(doc_uri).unwrap();
+                    let p = self.forward_params(changes, &b_new, transpile_changed);
+                    self.add_forwarded_changes(doc_uri, p, &self.uncommitted_bundle_changes);
+                }
+            };
+
+            rayon::join(transpile_task, bundle_task);
+        }
+    }
+
     fn forward_changes(
         &self,
         build: &Build,
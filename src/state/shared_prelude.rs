@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use async_lsp::lsp_types::Url as Uri;
+
+use crate::builder::{Build, Stack};
+use crate::proxy::Canonicalize;
+use crate::state::State;
+use crate::types::{Source, SourceHash};
+
+/// State of shared prelude
+///
+/// in shared prelude mode bundle of default document is opened in tsserver once as global
+/// script, other bundles reference it instead of inlining default document and its includes
+impl State {
+    pub fn enable_shared_prelude(&self) {
+        self.shared_prelude
+            .set(true)
+            .expect("shared prelude enabled once");
+    }
+
+    pub fn is_shared_prelude(&self) -> bool {
+        self.shared_prelude.get().is_some_and(|shared| *shared)
+    }
+
+    /// bundle of default document if shared prelude mode is enabled
+    pub fn get_shared_prelude(&self) -> Option<Arc<Build>> {
+        match self.is_shared_prelude() {
+            true => self.get_bundle(&self.get_default_doc()),
+            false => None,
+        }
+    }
+
+    /// shared prelude and its sources to skip by bundle of `target` (except `target` itself)
+    pub fn get_shared_prelude_for(
+        &self,
+        target: &Uri,
+    ) -> Option<(Arc<Build>, HashSet<SourceHash>)> {
+        let default_doc = self.get_default_doc();
+        if target.try_canonicalize() == default_doc {
+            return None;
+        }
+
+        let prelude = self.get_shared_prelude()?;
+        let target = self.get_doc_source_hash(target).ok()?;
+        let default_source = self.get_doc(&default_doc).ok()?.source;
+
+        let sources = prelude.sources_stack.keys().chain([&*default_source]);
+        let hashes = sources
+            .map(SourceHash::new)
+            .filter(|h| *h != target)
+            .collect();
+        Some((prelude, hashes))
+    }
+
+    /// sources of bundle with sources of shared prelude
+    pub fn get_bundle_sources(&self, bundle: &Build) -> HashSet<Source> {
+        let mut sources = bundle.sources();
+        if let Some(prelude) = self.get_shared_prelude()
+            && prelude.uri != bundle.uri
+        {
+            sources.extend(prelude.sources());
+        }
+        sources
+    }
+
+    /// [`Stack`] of source in bundle or (if it is skipped by bundle) in shared prelude
+    /// as if default document was inlined into bundle
+    pub fn get_source_stack(&self, bundle: &Build, source: &Source) -> Option<Stack> {
+        if let Some(stack) = bundle.sources_stack.get(source) {
+            return Some(stack.clone());
+        }
+
+        let prelude = self.get_shared_prelude().filter(|p| p.uri != bundle.uri)?;
+        if bundle.sources().contains(source) {
+            return None; // root source of bundle
+        }
+
+        let default_source = self.get_doc(&self.get_default_doc()).ok()?.source;
+        let mut stack = vec![((*default_source).clone(), (0, 0).into(), 0)];
+
+        if *source != *default_source {
+            stack.extend(prelude.sources_stack.get(source)?.iter().cloned());
+        }
+
+        Some(stack)
+    }
+
+    /// [`Build::sources_stack`] with stacks of sources skipped because of shared prelude
+    pub fn get_sources_stack(&self, bundle: &Build) -> HashMap<Source, Stack> {
+        let mut sources_stack = bundle.sources_stack.clone();
+        let Some(prelude) = self.get_shared_prelude().filter(|p| p.uri != bundle.uri) else {
+            return sources_stack;
+        };
+
+        let own_sources = bundle.sources();
+        let default_source = self.get_doc(&self.get_default_doc()).map(|d| d.source);
+        let prelude_sources = prelude.sources_stack.keys().cloned();
+        let prelude_sources = prelude_sources.chain(default_source.map(|s| (*s).clone()));
+
+        for source in prelude_sources {
+            if own_sources.contains(&source) {
+                continue;
+            }
+            if let Some(stack) = self.get_source_stack(bundle, &source) {
+                sources_stack.insert(source, stack);
+            }
+        }
+
+        sources_stack
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use crate::builder::IncludeIssueKind;
+    use crate::proxy::{DEFAULT_SCRIPT_FILENAME, PROXY_WORKSPACE};
+    use crate::state::testing::TestProject;
+    use crate::types::SourceHash;
+
+    /// project with shared prelude of default document which includes `lib/base.js`
+    fn project() -> TestProject {
+        let default_doc = format!("{PROXY_WORKSPACE}/{DEFAULT_SCRIPT_FILENAME}");
+        let files = [
+            (default_doc.as_str(), "#include <lib/base.js>\nvar d = 1;\n"),
+            ("lib/base.js", "var base = 1;\nvar base2 = 2;\n"),
+            ("main.js", "#include <lib/base.js>\nvar m = base;\n"),
+        ];
+        let project = TestProject::new(&files, json!({}));
+        project.state.enable_shared_prelude();
+        project
+            .state
+            .set_bundle(&project.state.get_default_doc())
+            .unwrap();
+        project
+    }
+
+    #[test]
+    fn prelude_sources_are_skipped_by_bundle() {
+        let project = project();
+        let st = &project.state;
+        let base = st.get_doc(&project.uri("lib/base.js")).unwrap().source;
+        let default_doc = st.get_default_doc();
+        let default_source = st.get_doc(&default_doc).unwrap().source;
+
+        assert!(st.get_shared_prelude_for(&default_doc).is_none());
+        let (prelude, skipped) = st.get_shared_prelude_for(&project.uri("main.js")).unwrap();
+        assert_eq!(prelude.uri, st.get_shared_prelude().unwrap().uri);
+        let expected = [SourceHash::new(&base), SourceHash::new(&default_source)];
+        assert_eq!(skipped, expected.into_iter().collect());
+
+        let bundle = project.bundle("main.js");
+        assert!(!bundle.content.contains("var base = 1;"));
+        assert!(!bundle.content.contains("var d = 1;"));
+        assert!(bundle.content.contains("var m = base;"));
+        assert!(bundle.content.contains("/// <reference path=\"./"));
+        assert!(!bundle.sources().contains(&base));
+        assert!(!bundle.sources().contains(&default_source));
+
+        // explicit include of prelude source is redundant
+        let issues = bundle.include_issues.iter().map(|i| (i.kind, &i.target));
+        assert_eq!(
+            issues.collect::<Vec<_>>(),
+            [(IncludeIssueKind::Redundant, &*base)]
+        );
+    }
+
+    #[test]
+    fn prelude_sources_are_stacked_under_default_document() {
+        let project = project();
+        let st = &project.state;
+        let base = (*st.get_doc(&project.uri("lib/base.js")).unwrap().source).clone();
+        let main = (*st.get_doc(&project.uri("main.js")).unwrap().source).clone();
+        let default_source = (*st.get_doc(&st.get_default_doc()).unwrap().source).clone();
+        let prelude = st.get_shared_prelude().unwrap();
+        let bundle = project.bundle("main.js");
+
+        // synthetic include of default document at the start of bundle
+        let default_stack = st.get_source_stack(&bundle, &default_source).unwrap();
+        assert_eq!(default_stack, [(default_source.clone(), (0, 0).into(), 0)]);
+
+        let base_stack = st.get_source_stack(&bundle, &base).unwrap();
+        let mut expected = default_stack.clone();
+        expected.extend(prelude.sources_stack[&base].iter().cloned());
+        assert_eq!(base_stack, expected);
+        assert_eq!(base_stack.last().map(|(s, _, _)| s), Some(&base));
+
+        // root source of bundle has no stack
+        assert!(st.get_source_stack(&bundle, &main).is_none());
+
+        let sources_stack = st.get_sources_stack(&bundle);
+        assert_eq!(sources_stack.get(&default_source), Some(&default_stack));
+        assert_eq!(sources_stack.get(&base), Some(&base_stack));
+        assert!(!sources_stack.contains_key(&main));
+
+        // prelude itself doesn't stack its sources under default document again
+        assert_eq!(st.get_sources_stack(&prelude), prelude.sources_stack);
+    }
+
+    #[test]
+    fn skipped_sources_are_forwarded_through_prelude() {
+        let project = project();
+        let st = &project.state;
+        let base = (*st.get_doc(&project.uri("lib/base.js")).unwrap().source).clone();
+        let prelude = st.get_shared_prelude().unwrap();
+        let bundle = project.bundle("main.js");
+
+        let pos = lsp::Position::new(1, 4);
+        assert_eq!(bundle.forward_src_position(&pos, &base), None);
+
+        let forwarded = prelude.forward_src_position(&pos, &base).unwrap();
+        let line = prelude
+            .content
+            .lines()
+            .nth(forwarded.line as usize)
+            .unwrap();
+        assert_eq!(&line[forwarded.character as usize..], "base2 = 2;");
+        assert!(st.get_bundle_sources(&bundle).contains(&base));
+    }
+}
@@ -0,0 +1,63 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_lsp::lsp_types::Url as Uri;
+
+use crate::builder::{Build, BuildOptionsBuilder};
+use crate::proxy::{DEFAULT_SCRIPT_FILENAME, PROXY_WORKSPACE};
+use crate::state::State;
+
+/// Temporary project on disk with state initialized like by native build commands
+pub struct TestProject {
+    pub state: State,
+    pub root: PathBuf,
+}
+
+impl TestProject {
+    /// project of `files` (paths relative to project root with content) and initialization
+    /// `options` of proxy
+    pub fn new(files: &[(&str, &str)], options: serde_json::Value) -> Self {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let id = COUNTER.fetch_add(1, Ordering::Relaxed);
+        let root = std::env::temp_dir().join(format!("glscript-test-{}-{id}", std::process::id()));
+
+        let proxy_ws = root.join(PROXY_WORKSPACE);
+        std::fs::create_dir_all(&proxy_ws).unwrap();
+        // sources are lowercased paths
+        std::fs::write(proxy_ws.join(DEFAULT_SCRIPT_FILENAME), "").unwrap();
+        std::fs::write(proxy_ws.join(DEFAULT_SCRIPT_FILENAME.to_lowercase()), "").unwrap();
+        for (path, content) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let root = dunce::canonicalize(root).unwrap();
+        let state = State::default();
+        state.initialize_project(&Uri::from_file_path(&root).unwrap(), None);
+        state.initialize_include_roots(Some(&options));
+        state.initialize_defines(Some(&options));
+        state.initialize_region_kinds(Some(&options));
+
+        Self { state, root }
+    }
+
+    pub fn uri(&self, path: &str) -> Uri {
+        Uri::from_file_path(self.root.join(path)).unwrap()
+    }
+
+    pub fn bundle(&self, path: &str) -> Build {
+        Build::create(BuildOptionsBuilder::init(&self.uri(path), &self.state)).unwrap()
+    }
+
+    pub fn transpile(&self, path: &str) -> Build {
+        let uri = self.uri(path);
+        Build::create(BuildOptionsBuilder::init(&uri, &self.state).transpile_mode()).unwrap()
+    }
+}
+
+impl Drop for TestProject {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
@@ -1,13 +1,16 @@
 use std::hash::{Hash, Hasher};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use async_lsp::lsp_types::{self as lsp, Url as Uri};
 use derive_more::{Constructor, Deref, Display, From, Into};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
-use crate::builder::Build;
-use crate::parser::{Parse, Token};
+use crate::builder::{Build, template_escapes};
+use crate::parser::{LineCol, Parse, SqlRegion, StringLiteral, Token};
+use crate::parser::{conditional_blocks, find_literal, region_kind};
 
 #[derive(Debug, Clone, Constructor)]
 pub struct BuildWithVersion {
@@ -27,6 +30,8 @@ pub struct Document {
     pub parse: Arc<Parse<'static>>,
     pub parse_content: Arc<String>, // needs for parse static lifetime
     pub buffer: ropey::Rope,
+    /// incremented on every content change
+    pub version: u32,
 
     pub transpile_hash: TranspileHash,
     pub decl_stmt: Arc<DocumentDeclarationStatement>,
@@ -34,7 +39,14 @@ pub struct Document {
 }
 
 impl Document {
+    /// position after the last include statement in `build` (none if it isn't mapped)
     pub fn first_non_include_build_pos(&self, build: &Build) -> Option<lsp::Position> {
+        self.first_non_include_pos()
+            .and_then(|source_pos| build.forward_src_position(&source_pos, &self.source))
+    }
+
+    /// position after the last include statement (or start of document without includes)
+    pub fn first_non_include_pos(&self) -> Option<lsp::Position> {
         self.parse
             .compressed_tokens
             .iter()
@@ -45,11 +57,11 @@ impl Document {
                 Token::Include(_) | Token::IncludePath(_) => unreachable!(),
                 Token::RegionOpen(s) | Token::RegionClose(s) => s.line_col.clone(),
                 Token::LineTerminator(lc) | Token::Eoi(lc) => lc.clone(),
-                Token::Common(rt) | Token::CommonWithLineEnding(rt) => rt.line_col.clone(),
+                Token::Directive(rt) | Token::Common(rt) | Token::CommonWithLineEnding(rt) => {
+                    rt.line_col.clone()
+                }
             })
             .map(|line_col| lsp::Position::new(line_col.line, line_col.col))
-            .map(|source_pos| build.forward_src_position(&source_pos, &self.source))
-            .map(Option::unwrap)
     }
 
     pub fn is_inside_include_path(&self, source_pos: &lsp::Position) -> bool {
@@ -67,13 +79,133 @@ impl Document {
             _ => false,
         })
     }
+
+    /// kind of region opened or closed by statement on `line`
+    pub fn get_region_kind(&self, line: u32) -> Option<&str> {
+        let start = self.buffer.try_line_to_byte(line as usize).ok()?;
+        region_kind(self.parse_content.get(start..)?.lines().next()?)
+    }
+
+    /// content of `#sql` regions
+    pub fn sql_regions(&self) -> Vec<SqlRegion> {
+        let mut regions = vec![];
+        let mut open = None;
+
+        for (idx, t) in self.parse.compressed_tokens.iter().enumerate() {
+            match t {
+                Token::RegionOpen(s) => open = Some((idx, s.line_col.line)),
+                Token::RegionClose(s) => {
+                    let Some((open_idx, open_line)) = open.take() else {
+                        continue;
+                    };
+
+                    if self.get_region_kind(open_line) != Some("sql") {
+                        continue;
+                    }
+
+                    let lines = open_line + 1..s.line_col.line;
+                    let text = (lines.start..lines.end)
+                        .filter_map(|l| self.buffer.get_line(l as usize))
+                        .map(String::from)
+                        .collect();
+                    regions.push(SqlRegion::new(open_idx, lines, text));
+                }
+                _ => {}
+            }
+        }
+
+        regions
+    }
+
+    /// identifier range (without `%`) and name of `%ident` interpolation under `source_pos`
+    pub fn get_interpolation(&self, source_pos: &lsp::Position) -> Option<(lsp::Range, String)> {
+        let interpolations = self.parse.str_interpolations.iter();
+        let mut on_line = interpolations.filter(|i| i.line == source_pos.line);
+        on_line.find_map(|i| {
+            let (range, name) = self.interpolation(i)?;
+            let start = range.start.character - 1;
+            (start..=range.end.character)
+                .contains(&source_pos.character)
+                .then_some((range, name))
+        })
+    }
+
+    /// start of identifier of `%ident` interpolation under `source_pos` (see
+    /// [`InterpolationReference`])
+    pub fn interpolation_target(&self, source_pos: &lsp::Position) -> Option<lsp::Position> {
+        let (range, _) = self.get_interpolation(source_pos)?;
+        Some(range.start.max(*source_pos))
+    }
+
+    /// start of string literal or region (expression position in build) if `%ident`
+    /// interpolation is typed in it before `source_pos`
+    pub fn interpolation_scope(&self, source_pos: &lsp::Position) -> Option<lsp::Position> {
+        let line = self.buffer.get_line(source_pos.line as usize)?;
+        let typed = line
+            .chars()
+            .take(source_pos.character as usize)
+            .collect::<String>();
+        let typed = typed.trim_end_matches(|c: char| c.is_alphanumeric() || c == '_');
+        let percents = typed.len() - typed.trim_end_matches('%').len();
+        if percents % 2 == 0 {
+            return None; // `%%` escapes
+        }
+
+        let pos = LineCol::new(source_pos.line, source_pos.character);
+        let start = find_literal(&self.parse_content, &pos)?;
+        Some(lsp::Position::new(start.line, start.col))
+    }
+
+    /// identifier ranges and names of `%ident` interpolations on `lines`
+    pub fn interpolations_on_lines(&self, lines: Range<u32>) -> Vec<(lsp::Range, String)> {
+        let interpolations = self.parse.str_interpolations.iter();
+        let on_lines = interpolations.filter(|i| lines.contains(&i.line));
+        on_lines.filter_map(|i| self.interpolation(i)).collect()
+    }
+
+    fn interpolation(&self, interpolation: &LineCol) -> Option<(lsp::Range, String)> {
+        let line = self.buffer.get_line(interpolation.line as usize)?;
+        let is_ident_char = |c: &char| c.is_alphanumeric() || *c == '_' || *c == '$';
+        let chars = line.chars().skip(interpolation.col as usize + 1);
+        let name = chars.take_while(is_ident_char).collect::<String>();
+        let start = lsp::Position::new(interpolation.line, interpolation.col + 1);
+        let len = name.encode_utf16().count() as u32;
+        let end = lsp::Position::new(start.line, start.character + len);
+        (!name.is_empty()).then(|| (lsp::Range::new(start, end), name))
+    }
+
+    /// include path under `source_pos`
+    pub fn get_include_path(&self, source_pos: &lsp::Position) -> Option<&StringLiteral<'static>> {
+        self.parse.compressed_tokens.iter().find_map(|t| match t {
+            Token::IncludePath(s) if s.line_col.line == source_pos.line => {
+                let (start, len) = (s.line_col.col, s.lit.len() as u32 + 2);
+                (start..=start + len)
+                    .contains(&source_pos.character)
+                    .then_some(s)
+            }
+            _ => None,
+        })
+    }
 }
 
 // TODO: refactor with from SourceMap::Token, LSP Uri (< SourceUri)
 /// must contains lowercase canonicalized strip prefixed path
 /// - is used as source in [`sourcemap`]
 /// - use to identify the source file
-#[derive(Debug, Eq, PartialEq, Hash, Clone, From, Into, Deref, Display, Constructor)]
+#[derive(
+    Debug,
+    Eq,
+    PartialEq,
+    Hash,
+    Clone,
+    From,
+    Into,
+    Deref,
+    Display,
+    Constructor,
+    Serialize,
+    Deserialize,
+)]
 pub struct Source(String);
 
 impl Source {
@@ -114,14 +246,16 @@ impl PartialEq for TranspileHash {
 type DocumentTranspileHashSources<'a> = (
     &'a Vec<Token<'a>>,
     Option<&'a [lsp::TextDocumentContentChangeEvent]>,
+    &'a [InterpolationReference],
 );
 
 impl From<DocumentTranspileHashSources<'_>> for TranspileHash {
     fn from(doc_sources: DocumentTranspileHashSources<'_>) -> Self {
-        let (tokens, changes) = doc_sources;
+        let (tokens, changes, references) = doc_sources;
         let hasher = &mut fxhash::FxHasher64::default();
         let changes = changes.unwrap_or(&[]);
 
+        let mut in_region = false;
         for t in tokens.iter() {
             let r_token = match t {
                 Token::IncludePath(path_lit) => {
@@ -133,6 +267,7 @@ impl From<DocumentTranspileHashSources<'_>> for TranspileHash {
                     lsp::Range::new(lsp::Position::new(ln, col), lsp::Position::new(ln, end_col))
                 }
                 Token::RegionOpen(span) | Token::RegionClose(span) => {
+                    in_region = matches!(t, Token::RegionOpen(_));
                     let (col, ln) = (span.line_col.col, span.line_col.line);
                     let end_col = ln + span.len;
                     col.hash(hasher);
@@ -140,6 +275,22 @@ impl From<DocumentTranspileHashSources<'_>> for TranspileHash {
                     span.len.hash(hasher);
                     lsp::Range::new(lsp::Position::new(ln, col), lsp::Position::new(ln, end_col))
                 }
+                Token::Directive(rt) => {
+                    let (col, ln) = (rt.line_col.col, rt.line_col.line);
+                    let end_col = col + rt.text.chars().count() as u32;
+                    col.hash(hasher);
+                    ln.hash(hasher);
+                    rt.text.hash(hasher);
+                    lsp::Range::new(lsp::Position::new(ln, col), lsp::Position::new(ln, end_col))
+                }
+                // escaped chars of region text shift build columns after them
+                Token::Common(rt) | Token::CommonWithLineEnding(rt) if in_region => {
+                    for offset in template_escapes(rt.text) {
+                        rt.line_col.line.hash(hasher);
+                        (rt.line_col.col + offset).hash(hasher);
+                    }
+                    continue;
+                }
                 _ => continue,
             };
 
@@ -154,6 +305,20 @@ impl From<DocumentTranspileHashSources<'_>> for TranspileHash {
             }
         }
 
+        // references of interpolations are emitted at the end of line
+        for r in references {
+            r.line.hash(hasher);
+            r.name.hash(hasher);
+        }
+
+        // branch is emitted as blank lines while it's inactive
+        for block in conditional_blocks(tokens) {
+            let touched = |r: &lsp::Range| r.start.line < block.end && block.start <= r.end.line;
+            if changes.iter().filter_map(|c| c.range.as_ref()).any(touched) {
+                return Self(None);
+            }
+        }
+
         Self(hasher.finish().into())
     }
 }
@@ -167,6 +332,55 @@ impl SourceHash {
     }
 }
 
+/// Root which include path literal is resolved from (directories are relative to project)
+#[derive(Debug, Clone, PartialEq, Eq, Display)]
+pub enum IncludeRoot {
+    /// `./` and `../` paths are relative to including document
+    #[display("relative path")]
+    Relative,
+    #[display("project root")]
+    Project,
+    #[display("include path `{_0}`")]
+    SearchPath(String),
+    /// prefix of literal replaced by directory
+    #[display("alias `{_0}` -> `{_1}`")]
+    Alias(String, String),
+}
+
+/// Include directories and aliases of angle-bracket includes which are tried in order:
+/// matched aliases, project root and then search paths
+#[derive(Debug, Default)]
+pub struct IncludeRoots {
+    pub search_paths: Vec<String>,
+    pub aliases: Vec<(String, String)>,
+}
+
+/// Emit wrapper of region template literal and semantic token type of region content
+/// (configured by region kind)
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RegionKind {
+    /// emitted before template literal: tag like `sql` or call like `JSON.parse(`
+    pub prefix: String,
+    /// emitted after template literal: like `)` of call
+    pub suffix: String,
+    pub token_type: Option<lsp::SemanticTokenType>,
+    /// `%ident` interpolations of region text are referenced after template literal in all
+    /// builds (see [`InterpolationReference`]) and TSServer reports undeclared ones
+    pub check_interpolations: bool,
+}
+
+/// `%ident` interpolation which is referenced in build after the end of `line` (like
+/// `typeof ident;`): requests on interpolation are resolved by TSServer at its reference
+#[derive(Debug, Clone, Constructor)]
+pub struct InterpolationReference {
+    pub line: u32,
+    pub range: lsp::Range,
+    pub name: String,
+    /// undeclared identifier is reported (see [`RegionKind::check_interpolations`])
+    pub checked: bool,
+}
+
 #[derive(Constructor, Clone)]
 pub struct SourcePattern<'a> {
     pub lit: &'a str,
@@ -235,3 +449,27 @@ impl DocumentLinkStatement {
         Self::new(LEFT_OFFSET as u32, RIGHT_OFFSET as u32, undefined_stmt)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use async_lsp::lsp_types as lsp;
+    use serde_json::json;
+
+    use crate::state::testing::TestProject;
+
+    #[test]
+    fn first_non_include_position_is_forwarded_if_mapped() {
+        let files = [("main.js", "#include \"dep.js\"\nfoo();"), ("dep.js", "")];
+        let project = TestProject::new(&files, json!({}));
+        let (main, dep) = (project.bundle("main.js"), project.bundle("dep.js"));
+        let doc = project.state.get_doc(&project.uri("main.js")).unwrap();
+
+        let pos = doc.first_non_include_pos().unwrap();
+        assert_eq!(pos, lsp::Position::new(0, 17));
+        let build_pos = doc.first_non_include_build_pos(&main).unwrap();
+        assert_eq!(main.forward_build_position(&build_pos).unwrap().0, pos);
+
+        // build without document
+        assert_eq!(doc.first_non_include_build_pos(&dep), None);
+    }
+}